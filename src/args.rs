@@ -0,0 +1,405 @@
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{value_parser, ArgMatches, CommandFactory, Parser};
+use pngquant_interactive::attributes::AdvancedAttributes;
+use pngquant_interactive::color_key::ColorKey;
+use pngquant_interactive::crop::Crop;
+use pngquant_interactive::debug;
+use pngquant_interactive::encode::Priority;
+use pngquant_interactive::export::{self, is_stdin, Exporter};
+use pngquant_interactive::filter::Filter;
+use pngquant_interactive::preview::{Params, Preview};
+use pngquant_interactive::source::Source;
+use pngquant_interactive::template::NameTemplate;
+use pngquant_interactive::transform::parse_weights;
+use pngquant_interactive::utilities::{
+    parse_gamma, parse_palette_path, parse_palette_size, parse_percentage, parse_rgb, parse_rgba,
+};
+use rgb::{RGB8, RGBA8};
+use std::path::{Path, PathBuf};
+
+/// Parameters when none are given or remembered
+pub const DEFAULTS: Params = Params {
+    colors: 256,
+    dithering: 0,
+    effort: 10,
+    preservation: 50,
+    posterization: 0,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    after_help = "Copying the preview in the GUI requires wl-copy (Wayland) or xclip (X11)."
+)]
+pub struct Args {
+    /// Speed–quality tradeoff (speed <11−E>) 1–10
+    #[arg(long, short, value_name = "E", default_value_t = DEFAULTS.effort, value_parser = value_parser!(u8).range(1..=10))]
+    pub effort: u8,
+
+    /// Color preservation cutoff (quality 0-<P>) 0–100
+    #[arg(long, short, value_name = "P", default_value_t = DEFAULTS.preservation, value_parser = value_parser!(u8).range(0..=100))]
+    pub preservation: u8,
+
+    /// Maximum palette size (ncolors <N>) 2–256
+    #[arg(long, short, value_name = "N", default_value_t = DEFAULTS.colors, value_parser = value_parser!(u16).range(2..=256))]
+    pub colors: u16,
+
+    /// Amount of dithering (floyd <D∕10>) 0–10
+    ///
+    /// Error is always diffused in serpentine order, alternating direction on each row.
+    #[arg(long, short, value_name = "D", default_value_t = DEFAULTS.dithering, value_parser = value_parser!(u8).range(0..=10))]
+    pub dithering: u8,
+
+    /// Bits of precision to discard (posterize <B>) 0–4
+    #[arg(long, value_name = "B", default_value_t = DEFAULTS.posterization, value_parser = value_parser!(u8).range(0..=4))]
+    pub posterization: u8,
+
+    /// Start from the parameters in this JSON file, overridden by any given above
+    #[arg(long, value_name = "PATH")]
+    pub load_preset: Option<PathBuf>,
+
+    /// Save the exported parameters to this JSON file
+    ///
+    /// In a batch, these are the parameters given rather than any fitted to --max-bytes.
+    #[arg(long, value_name = "PATH", conflicts_with = "compare_to")]
+    pub save_preset: Option<PathBuf>,
+
+    /// Overwrite existing output files without asking
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Allow the output to replace the source file
+    #[arg(long)]
+    pub in_place: bool,
+
+    /// Losslessly optimize exports further with oxipng
+    #[cfg(feature = "oxipng")]
+    #[arg(long, conflicts_with = "stdout")]
+    pub optimize: bool,
+
+    /// Copy the source file instead when the export would be larger
+    #[arg(long, conflicts_with = "stdout")]
+    pub keep_smaller: bool,
+
+    /// Recompress without reducing colors, keeping grayscale as such
+    ///
+    /// Sources with 16 bits per channel or a palette are refused rather than converted.
+    #[arg(long, conflicts_with_all = ["atlas", "compare_to"])]
+    pub lossless: bool,
+
+    /// Output file or directory, or - for standard output [default: beside the source]
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Suffix of output file name [default: "-fs8", "-or8", or "-opt"]
+    #[arg(long, short = 'u')]
+    pub suffix: Option<String>,
+
+    /// Name of output files, e.g. {stem}-{colors}c-q{quality}.png
+    ///
+    /// Tokens: {stem} of the source, {suffix}, and the {colors}, {quality} (preservation),
+    /// {dither}, and {effort} exported with.
+    #[arg(long, value_name = "TEMPLATE", default_value = "{stem}{suffix}.png")]
+    pub name_template: NameTemplate,
+
+    /// JSON file of imagequant settings, overriding effort and preservation where given
+    ///
+    /// Fields: `min_quality`, `max_quality`, `speed`, `max_colors`, `min_posterization`
+    #[arg(long, value_name = "PATH")]
+    pub attributes: Option<PathBuf>,
+
+    /// Relative importance of channels when grouping colors, e.g. 1,1,1,0.5 [default: perceptual]
+    ///
+    /// Color channels are scaled by weight before quantization and the palette is then taken from
+    /// the mean of the original pixels mapped to each entry. The alpha weight instead lowers the
+    /// importance of translucent pixels.
+    #[arg(long, value_name = "R,G,B,A", value_parser = parse_weights)]
+    pub weight: Option<[f32; 4]>,
+
+    /// Group colors by their linear-light values (experimental)
+    ///
+    /// Linear values are kept in 8 bits, so the darkest shades are grouped more coarsely.
+    #[arg(long)]
+    pub linear: bool,
+
+    /// Use color-key transparency with the given or a free color [values: RRGGBB, auto]
+    ///
+    /// Export fails if any color in the palette is partly transparent.
+    #[arg(long, value_name = "COLOR")]
+    pub color_key: Option<ColorKey>,
+
+    /// Include this exact color in the palette, counting toward the maximum (repeatable)
+    #[arg(long, value_name = "RRGGBBAA", value_parser = parse_rgba, conflicts_with_all = ["linear", "weight"])]
+    pub fixed_color: Vec<RGBA8>,
+
+    /// Place the transparent color at the end of the palette, as some decoders expect
+    #[arg(long, conflicts_with_all = ["color_key", "palette_size"])]
+    pub last_index_transparent: bool,
+
+    /// Composite the source over this color, exporting an opaque image [values: RRGGBB]
+    #[arg(long, value_name = "COLOR", value_parser = parse_rgb, conflicts_with_all = ["background", "color_key", "last_index_transparent"])]
+    pub flatten: Option<RGB8>,
+
+    /// Preview over this color, remapping for display on it [values: RRGGBB]
+    #[arg(long, value_name = "COLOR", value_parser = parse_rgb)]
+    pub background: Option<RGB8>,
+
+    /// Remap to this gamma and declare it, replacing any source color space [values: 0–1]
+    #[arg(long, value_name = "GAMMA", value_parser = parse_gamma)]
+    pub output_gamma: Option<f64>,
+
+    /// Also export the palette to this GIMP (.gpl) or Adobe (.act) palette file
+    #[arg(long, value_name = "PATH", value_parser = parse_palette_path, conflicts_with_all = ["batch", "compare_to", "lossless"])]
+    pub export_palette: Option<PathBuf>,
+
+    /// Pad the palette to exactly this many entries, a power of two
+    #[arg(long, value_name = "N", value_parser = parse_palette_size)]
+    pub palette_size: Option<usize>,
+
+    /// Filter applied to rows before compression [values: none, sub, up, avg, paeth, adaptive]
+    ///
+    /// Defaults to sub, or to adaptive when recompressing without reducing colors.
+    #[arg(long, value_name = "FILTER")]
+    pub filter: Option<Filter>,
+
+    /// Interlace the output with Adam7 so that it renders progressively
+    #[arg(long, conflicts_with = "lossless")]
+    pub interlace: bool,
+
+    /// Write grayscale instead of indexed color when the palette is opaque and entirely gray
+    #[arg(long, conflicts_with = "lossless")]
+    pub grayscale: bool,
+
+    /// Omit text metadata
+    #[arg(long)]
+    pub strip_text: bool,
+
+    /// Copy all ancillary chunks that remain valid after quantization
+    #[arg(long)]
+    pub preserve_all_metadata: bool,
+
+    /// Find the preview palette from a sample of large sources, still exporting at full resolution
+    ///
+    /// Toggled with F in the GUI.
+    #[arg(long)]
+    pub draft: bool,
+
+    /// Find the palette from only this rectangle of the source, still remapping all of it
+    ///
+    /// Toggled with P in the GUI to follow the crop of the view.
+    #[arg(long, value_name = "X,Y,W,H", conflicts_with_all = ["atlas", "lossless"])]
+    pub palette_region: Option<Crop>,
+
+    /// Estimate size with the slower compression used for export
+    ///
+    /// Runs in the background after each preview; estimates superseded by newer parameters are
+    /// discarded.
+    #[arg(long)]
+    pub exact_estimate: bool,
+
+    /// Choose the highest preservation estimated to fit within this size
+    #[arg(long, value_name = "BYTES", conflicts_with_all = ["atlas", "compare_to", "lossless"])]
+    pub max_bytes: Option<usize>,
+
+    /// Report details such as chunks dropped, cache hits, and where exports went
+    #[arg(long, short, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    /// Report only warnings and errors
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Write the result to standard output instead of starting the GUI
+    #[arg(long, conflicts_with_all = ["atlas", "compare_to", "lossless"])]
+    pub stdout: bool,
+
+    /// Export without starting the GUI, and describe the result as JSON on standard output
+    #[arg(long, conflicts_with_all = ["atlas", "batch", "compare_to", "stdout"])]
+    pub json: bool,
+
+    /// Compare against a known-good PNG instead of starting the GUI
+    #[arg(long, value_name = "PATH")]
+    pub compare_to: Option<PathBuf>,
+
+    /// Tolerated growth in size relative to the baseline
+    #[arg(long, value_name = "PERCENT", default_value = "0%", value_parser = parse_percentage, requires = "compare_to")]
+    pub max_size_delta: f64,
+
+    /// Tolerated proportion of pixels differing from the baseline
+    #[arg(long, value_name = "PERCENT", default_value = "0%", value_parser = parse_percentage, requires = "compare_to")]
+    pub max_pixel_diff: f64,
+
+    /// Quantize these along with the source to a shared palette, exporting each
+    #[arg(long, value_name = "PATH", num_args = 1..)]
+    pub atlas: Vec<PathBuf>,
+
+    /// Also export the combined sheet of atlas sources
+    #[arg(long, value_name = "PATH", requires = "atlas")]
+    pub atlas_sheet: Option<PathBuf>,
+
+    /// Process all sources with the given settings instead of starting the GUI
+    #[arg(long, conflicts_with_all = ["atlas", "compare_to", "stdout"])]
+    pub batch: bool,
+
+    /// Source PNG file, or - for standard input; chosen in a dialog if omitted
+    #[arg()]
+    pub path: Option<PathBuf>,
+
+    /// Further source PNG files for --batch
+    #[arg(requires = "batch")]
+    pub more_paths: Vec<PathBuf>,
+}
+
+impl Args {
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().expect("chosen")
+    }
+
+    /// Take parameters given explicitly on the command line, and the rest from the base
+    pub fn overriding(&self, base: &Params, matches: &ArgMatches) -> Params {
+        let explicit = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+        Params {
+            colors: if explicit("colors") {
+                self.colors
+            } else {
+                base.colors
+            },
+            dithering: if explicit("dithering") {
+                self.dithering
+            } else {
+                base.dithering
+            },
+            effort: if explicit("effort") {
+                self.effort
+            } else {
+                base.effort
+            },
+            preservation: if explicit("preservation") {
+                self.preservation
+            } else {
+                base.preservation
+            },
+            posterization: if explicit("posterization") {
+                self.posterization
+            } else {
+                base.posterization
+            },
+        }
+    }
+
+    /// Take --output - as --stdout, refusing the options --stdout conflicts with
+    pub fn output_to_stdout(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        if !self.output.as_deref().is_some_and(is_stdin) {
+            return Ok(());
+        }
+        let mut command = Self::command();
+        let stdout = command
+            .get_arguments()
+            .find(|a| a.get_id() == "stdout")
+            .expect("stdout");
+        let conflicting = command.get_arguments().find(|a| {
+            let id = a.get_id();
+            let conflicts = command.get_arg_conflicts_with(stdout).contains(a)
+                || command.get_arg_conflicts_with(a).contains(&stdout);
+            conflicts && matches.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = conflicting {
+            let name = arg.get_long().map_or_else(
+                || arg.get_id().to_string().to_uppercase(),
+                |l| format!("--{l}"),
+            );
+            let message = format!("the argument '--output -' cannot be used with '{name}'");
+            return Err(command.error(ErrorKind::ArgumentConflict, message));
+        }
+        self.stdout = true;
+        self.output = None;
+        Ok(())
+    }
+
+    pub const fn estimate_priority(&self) -> Priority {
+        if self.exact_estimate {
+            Priority::Size
+        } else {
+            Priority::Speed
+        }
+    }
+
+    /// Whether to work without showing the GUI
+    pub const fn headless(&self) -> bool {
+        self.compare_to.is_some()
+            || !self.atlas.is_empty()
+            || self.batch
+            || self.stdout
+            || self.json
+    }
+
+    pub fn exporter(&self) -> Exporter {
+        Exporter {
+            output: self.output.clone(),
+            suffix: self.suffix.clone(),
+            name_template: self.name_template.clone(),
+            lossless: self.lossless,
+            multiple: self.batch || !self.atlas.is_empty(),
+            force: self.force,
+            in_place: self.in_place,
+            keep_smaller: self.keep_smaller,
+            #[cfg(feature = "oxipng")]
+            optimize: self.optimize,
+            #[cfg(feature = "oxipng")]
+            fixed_palette: self.palette_size.is_some()
+                || self.last_index_transparent
+                || self.color_key.is_some(),
+        }
+    }
+
+    pub fn load(&self, path: &Path) -> Result<Source> {
+        let data = export::read(path)?;
+        let mut source = Source::try_from(data.as_slice())?;
+        if self.lossless {
+            source.check_lossless()?;
+        }
+        debug!(
+            "{}: {}×{} {}, {} bytes",
+            path.display(),
+            source.width,
+            source.height,
+            if source.uses_alpha { "RGBA" } else { "RGB" },
+            data.len()
+        );
+        for warning in source.warnings() {
+            debug!("{}: {warning}", path.display());
+        }
+        let (kept, dropped): (Vec<_>, _) = source.chunks.drain(..).partition(|c| {
+            (self.preserve_all_metadata || c.is_preserved()) && !(self.strip_text && c.is_text())
+        });
+        for chunk in &dropped {
+            debug!("{}: dropping {} chunk", path.display(), chunk.name());
+        }
+        source.chunks = kept;
+        source.filter = self.filter;
+        if let Some(color) = self.flatten {
+            source.flatten(color);
+        }
+        Ok(source)
+    }
+
+    pub fn preview(&self, source: Source, attributes: &AdvancedAttributes) -> Preview {
+        let mut preview = Preview::from(source);
+        preview.attributes = attributes.clone();
+        preview.color_key = self.color_key;
+        preview.weights = self.weight;
+        preview.linear = self.linear;
+        preview.draft = self.draft;
+        preview.fixed_colors.clone_from(&self.fixed_color);
+        preview.last_index_transparent = self.last_index_transparent;
+        preview.background = self.background;
+        preview.output_gamma = self.output_gamma;
+        preview.interlace = self.interlace;
+        preview.grayscale = self.grayscale;
+        preview.palette_size = self.palette_size;
+        preview.set_palette_region(self.palette_region);
+        preview
+    }
+}
@@ -0,0 +1,107 @@
+use crate::chunks::Chunk;
+use crate::source::Source;
+use anyhow::{bail, Error, Result};
+use png::chunk::ChunkType;
+use rgb::RGBA8;
+
+pub struct Tile {
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Metadata of the source, kept in its export
+    pub chunks: Vec<Chunk>,
+}
+
+pub struct Atlas {
+    pub sheet: Source,
+    pub importance: Vec<u8>,
+    pub tiles: Vec<Tile>,
+}
+
+impl TryFrom<Vec<Source>> for Atlas {
+    type Error = Error;
+
+    /// Stack sources vertically, excluding the padding from quantization by giving it no importance,
+    /// provided they share a color space
+    fn try_from(sources: Vec<Source>) -> Result<Self> {
+        if let Some(first) = sources.first() {
+            if sources.iter().any(|s| color_space(s) != color_space(first)) {
+                bail!("atlas sources differ in color space (cHRM, cICP, gAMA, iCCP, or sRGB)");
+            }
+        }
+        let width = sources.iter().map(|s| s.width).max().unwrap_or(0);
+        let mut rgba = Vec::new();
+        let mut importance = Vec::new();
+        let mut tiles = Vec::new();
+
+        for source in &sources {
+            tiles.push(Tile {
+                y: tiles.last().map_or(0, |t: &Tile| t.y + t.height),
+                width: source.width,
+                height: source.height,
+                chunks: source.chunks.clone(),
+            });
+            for row in source.rgba.chunks_exact(source.width) {
+                rgba.extend_from_slice(row);
+                rgba.resize(rgba.len() + width - source.width, RGBA8::default());
+                importance.resize(importance.len() + source.width, u8::MAX);
+                importance.resize(importance.len() + width - source.width, 0);
+            }
+        }
+
+        Ok(Self {
+            sheet: Source {
+                uses_alpha: sources.iter().any(|s| s.uses_alpha),
+                bit_depth_reduced: sources.iter().any(|s| s.bit_depth_reduced),
+                was_indexed: sources.iter().any(|s| s.was_indexed),
+                width,
+                height: tiles.last().map_or(0, |t| t.y + t.height),
+                rgba,
+                chunks: (sources.first().map(|s| s.chunks.as_slice()))
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|c| c.is_color())
+                    .cloned()
+                    .collect(),
+                filter: sources.first().and_then(|s| s.filter),
+            },
+            importance,
+            tiles,
+        })
+    }
+}
+
+/// Chunks of a source describing its color space
+fn color_space(source: &Source) -> Vec<(ChunkType, &[u8])> {
+    (source.chunks.iter())
+        .filter(|c| c.is_color())
+        .map(|c| (c.kind, c.data.as_slice()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use png::chunk;
+
+    const CHRM: &[u8] = include_bytes!("../tests/fixtures/chrm.png");
+    const PHYS: &[u8] = include_bytes!("../tests/fixtures/phys.png");
+
+    fn source(data: &[u8]) -> Source {
+        Source::try_from(data).unwrap()
+    }
+
+    #[test]
+    fn refuses_sources_in_different_color_spaces() {
+        assert!(Atlas::try_from(vec![source(CHRM), source(PHYS)]).is_err());
+    }
+
+    #[test]
+    fn keeps_metadata_of_each_tile() {
+        let atlas = Atlas::try_from(vec![source(PHYS), source(PHYS)]).unwrap();
+        assert!(atlas.sheet.chunks.is_empty());
+        for tile in &atlas.tiles {
+            assert!(tile.chunks.iter().any(|c| c.kind == chunk::pHYs));
+        }
+    }
+}
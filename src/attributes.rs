@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+/// Settings for imagequant beyond those exposed by the interface
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdvancedAttributes {
+    pub min_quality: Option<u8>,
+    pub max_quality: Option<u8>,
+    pub speed: Option<u8>,
+    pub max_colors: Option<u16>,
+    pub min_posterization: Option<u8>,
+}
+
+impl AdvancedAttributes {
+    pub fn load(path: &Path) -> Result<Self> {
+        let attributes: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        macro_rules! validate {
+            ($field:ident, $range:expr) => {
+                if let Some(value) = attributes.$field {
+                    if !$range.contains(&value) {
+                        bail!("{} must be within {:?}", stringify!($field), $range);
+                    }
+                }
+            };
+        }
+        validate!(min_quality, 0..=100);
+        validate!(max_quality, 0..=100);
+        validate!(speed, 1..=10);
+        validate!(max_colors, 2..=256);
+        validate!(min_posterization, 0..=4);
+        if let (Some(min), Some(max)) = (attributes.min_quality, attributes.max_quality) {
+            if min > max {
+                bail!("min_quality must not exceed max_quality");
+            }
+        }
+
+        Ok(attributes)
+    }
+}
+
+impl Display for AdvancedAttributes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        macro_rules! field {
+            ($field:ident) => {
+                if let Some(value) = self.$field {
+                    writeln!(f, "{}: {value}", stringify!($field))?;
+                }
+            };
+        }
+        field!(min_quality);
+        field!(max_quality);
+        field!(speed);
+        field!(max_colors);
+        field!(min_posterization);
+        Ok(())
+    }
+}
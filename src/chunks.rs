@@ -0,0 +1,258 @@
+use crate::debug;
+use anyhow::{bail, Result};
+use flate2::read::ZlibDecoder;
+use png::chunk::{self, ChunkType};
+use png::Writer;
+use std::io::{Read, Write};
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Chunks describing the image rather than its encoding, and so still valid after quantization
+const INDEPENDENT: [ChunkType; 6] = [
+    chunk::cHRM,
+    chunk::gAMA,
+    chunk::iCCP,
+    chunk::sRGB,
+    chunk::tIME,
+    ChunkType(*b"cICP"),
+];
+
+/// Chunks copied as found, without synthesizing any implied by others, even without
+/// --preserve-all-metadata
+const PRESERVED: [ChunkType; 8] = [
+    chunk::cHRM,
+    chunk::gAMA,
+    chunk::iCCP,
+    chunk::iTXt,
+    chunk::pHYs,
+    chunk::sRGB,
+    chunk::tEXt,
+    chunk::zTXt,
+];
+
+/// Chunks describing the color space, which sources quantized together must share
+const COLOR: [ChunkType; 5] = [
+    chunk::cHRM,
+    ChunkType(*b"cICP"),
+    chunk::gAMA,
+    chunk::iCCP,
+    chunk::sRGB,
+];
+
+/// Standard ancillary chunks, which are only copied when listed above
+const STANDARD: [ChunkType; 19] = [
+    chunk::acTL,
+    chunk::bKGD,
+    chunk::cHRM,
+    ChunkType(*b"cICP"),
+    ChunkType(*b"eXIf"),
+    chunk::fcTL,
+    chunk::fdAT,
+    chunk::gAMA,
+    ChunkType(*b"hIST"),
+    chunk::iCCP,
+    chunk::iTXt,
+    chunk::pHYs,
+    ChunkType(*b"sBIT"),
+    ChunkType(*b"sPLT"),
+    chunk::sRGB,
+    chunk::tEXt,
+    chunk::tIME,
+    chunk::tRNS,
+    chunk::zTXt,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    BeforePlte,
+    BeforeIdat,
+    AfterIdat,
+}
+
+#[derive(Clone)]
+pub struct Chunk {
+    pub kind: ChunkType,
+    pub data: Vec<u8>,
+    pub position: Position,
+}
+
+impl Chunk {
+    pub fn gamma(gamma: f64) -> Self {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let value = (gamma * 100_000.0).round() as u32;
+        Self {
+            kind: chunk::gAMA,
+            data: value.to_be_bytes().to_vec(),
+            position: Position::BeforePlte,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.kind.0).into_owned()
+    }
+
+    pub fn is_copyable(&self) -> bool {
+        let ChunkType([_, _, _, last]) = self.kind;
+        last.is_ascii_lowercase() || INDEPENDENT.contains(&self.kind)
+    }
+
+    pub fn is_color(&self) -> bool {
+        COLOR.contains(&self.kind)
+    }
+
+    pub fn is_preserved(&self) -> bool {
+        let ChunkType([_, _, _, last]) = self.kind;
+        PRESERVED.contains(&self.kind)
+            || (!STANDARD.contains(&self.kind) && last.is_ascii_lowercase())
+    }
+
+    /// Color space named in the header of an ICC profile
+    fn icc_space(&self) -> Option<[u8; 4]> {
+        let name_end = self.data.iter().position(|&b| b == 0)?;
+        let compressed = self.data.get(name_end + 2..)?;
+        let mut header = [0; 20];
+        ZlibDecoder::new(compressed).read_exact(&mut header).ok()?;
+        header[16..20].try_into().ok()
+    }
+
+    /// Whether the chunk agrees with output in grayscale or in color
+    pub fn suits(&self, gray: bool) -> bool {
+        self.kind != chunk::iCCP
+            || self
+                .icc_space()
+                .is_some_and(|space| (&space == b"GRAY") == gray)
+    }
+
+    pub fn is_text(&self) -> bool {
+        [chunk::iTXt, chunk::tEXt, chunk::zTXt].contains(&self.kind)
+    }
+
+    /// Read the ancillary chunks of an encoded PNG
+    pub fn read_all(mut data: &[u8]) -> Result<Vec<Self>> {
+        let Some(rest) = data.strip_prefix(&SIGNATURE) else {
+            bail!("not a PNG");
+        };
+        data = rest;
+
+        let mut chunks = Vec::new();
+        let mut position = Position::BeforePlte;
+        while !data.is_empty() {
+            if data.len() < 12 {
+                bail!("truncated chunk");
+            }
+            let length = usize::try_from(u32::from_be_bytes(data[0..4].try_into()?))?;
+            let kind = ChunkType(data[4..8].try_into()?);
+            let Some(body) = data.get(8..8 + length) else {
+                bail!("truncated chunk");
+            };
+
+            match kind {
+                chunk::PLTE => position = Position::BeforeIdat,
+                chunk::IDAT => position = Position::AfterIdat,
+                chunk::IEND => break,
+                ChunkType([first, ..]) if first.is_ascii_lowercase() => chunks.push(Self {
+                    kind,
+                    data: body.to_vec(),
+                    position,
+                }),
+                _ => {}
+            }
+            data = data.get(12 + length..).unwrap_or_default();
+        }
+        Ok(chunks)
+    }
+}
+
+pub fn write_chunks<W: Write>(
+    writer: &mut Writer<W>,
+    chunks: &[Chunk],
+    position: Position,
+    gray: bool,
+) -> Result<()> {
+    for chunk in chunks.iter().filter(|c| c.position == position) {
+        if !chunk.is_copyable() {
+            debug!("Dropping {} chunk unsafe to copy", chunk.name());
+        } else if !chunk.suits(gray) {
+            debug!("Dropping {} chunk unsuited to the output", chunk.name());
+        } else {
+            debug!("Keeping {} chunk", chunk.name());
+            writer.write_chunk(chunk.kind, &chunk.data)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::Params;
+
+    const CHRM: &[u8] = include_bytes!("../tests/fixtures/chrm.png");
+    const PHYS: &[u8] = include_bytes!("../tests/fixtures/phys.png");
+
+    const PARAMS: Params = Params {
+        colors: 16,
+        dithering: 0,
+        effort: 5,
+        preservation: 100,
+        posterization: 0,
+    };
+
+    fn find(chunks: &[Chunk], kind: ChunkType) -> Option<&Chunk> {
+        chunks.iter().find(|c| c.kind == kind)
+    }
+
+    #[test]
+    fn reads_ancillary_chunks_in_position() {
+        let chunks = Chunk::read_all(CHRM).unwrap();
+        assert_eq!(chunks.len(), 1);
+        let chrm = find(&chunks, chunk::cHRM).unwrap();
+        assert_eq!(chrm.data.len(), 32);
+        assert!(chrm.position == Position::BeforePlte);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        assert!(Chunk::read_all(b"GIF89a").is_err());
+        assert!(Chunk::read_all(&CHRM[..CHRM.len() - 20]).is_err());
+    }
+
+    #[test]
+    fn keeps_chromaticities_through_quantization() {
+        let output = crate::quantize_png(CHRM, &PARAMS).unwrap();
+        let before = Chunk::read_all(CHRM).unwrap();
+        let after = Chunk::read_all(&output).unwrap();
+        assert_eq!(
+            find(&after, chunk::cHRM).unwrap().data,
+            find(&before, chunk::cHRM).unwrap().data
+        );
+    }
+
+    #[test]
+    fn drops_timestamps_through_quantization() {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 4, 4);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_chunk(chunk::tIME, &[0x07, 0xea, 10, 16, 12, 0, 0])
+            .unwrap();
+        writer.write_image_data(&[0x80; 4 * 4 * 3]).unwrap();
+        writer.finish().unwrap();
+        let output = crate::quantize_png(&data, &PARAMS).unwrap();
+        assert!(find(&Chunk::read_all(&data).unwrap(), chunk::tIME).is_some());
+        assert!(find(&Chunk::read_all(&output).unwrap(), chunk::tIME).is_none());
+    }
+
+    #[test]
+    fn keeps_physical_dimensions_through_quantization() {
+        let dimensions = |data: &[u8]| {
+            let reader = png::Decoder::new(data).read_info().unwrap();
+            let png::PixelDimensions { xppu, yppu, unit } = reader.info().pixel_dims.unwrap();
+            (xppu, yppu, unit == png::Unit::Meter)
+        };
+        let output = crate::quantize_png(PHYS, &PARAMS).unwrap();
+        assert_eq!(dimensions(PHYS), (11811, 5906, true));
+        assert_eq!(dimensions(&output), dimensions(PHYS));
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::{bail, Result};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Programs that can place an image on the clipboard, since FLTK can only copy text
+const COPIERS: [(&str, &[&str]); 2] = [
+    ("wl-copy", &["--type", "image/png"]),
+    (
+        "xclip",
+        &["-selection", "clipboard", "-target", "image/png", "-in"],
+    ),
+];
+
+/// Place PNG data on the system clipboard
+pub fn copy_png(data: &[u8]) -> Result<()> {
+    for (program, args) in COPIERS {
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            result => result?,
+        };
+        child.stdin.take().expect("piped").write_all(data)?;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{program} failed with {status}");
+        }
+        return Ok(());
+    }
+    bail!("copying an image requires wl-copy or xclip");
+}
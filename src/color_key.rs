@@ -0,0 +1,105 @@
+use crate::utilities::parse_rgb;
+use anyhow::{bail, Result};
+use rgb::{RGB8, RGBA8};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ColorKey {
+    Auto,
+    Rgb(RGB8),
+}
+
+impl ColorKey {
+    /// Merge fully transparent entries into a single key entry at index 0, refusing any partly
+    /// transparent entries
+    pub fn apply(self, palette: &[RGBA8], indices: &[u8]) -> Result<(Vec<RGBA8>, Vec<u8>)> {
+        let partial = palette.iter().filter(|p| 0 < p.a && p.a < u8::MAX).count();
+        if partial > 0 {
+            bail!("{partial} colors are partly transparent, which a color key can’t represent");
+        }
+        let opaque: Vec<RGB8> = palette.iter().filter(|p| p.a > 0).map(RGBA8::rgb).collect();
+        if opaque.len() > usize::from(u8::MAX) {
+            bail!("palette is full; no index is free for a color key");
+        }
+        let key = match self {
+            Self::Rgb(key) if opaque.contains(&key) => {
+                bail!("color key {key} is used by the image")
+            }
+            Self::Rgb(key) => key,
+            Self::Auto => (0..=0xFF_FFFF_u32)
+                .map(|n| (n + 0xFF_00FF) & 0xFF_FFFF)
+                .map(|n| {
+                    let [_, r, g, b] = n.to_be_bytes();
+                    RGB8::new(r, g, b)
+                })
+                .find(|c| !opaque.contains(c))
+                .expect("free color"),
+        };
+
+        let mut keyed = vec![key.with_alpha(0)];
+        let mapping: Vec<u8> = palette
+            .iter()
+            .map(|p| {
+                if p.a == 0 {
+                    Ok(0)
+                } else {
+                    keyed.push(*p);
+                    u8::try_from(keyed.len() - 1)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        let indices = indices.iter().map(|&i| mapping[usize::from(i)]).collect();
+
+        Ok((keyed, indices))
+    }
+}
+
+impl FromStr for ColorKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            _ => parse_rgb(s).map(Self::Rgb),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGBA8 = RGBA8::new(0xFF, 0, 0, 0xFF);
+    const BLUE: RGBA8 = RGBA8::new(0, 0, 0xFF, 0xFF);
+    const CLEAR: RGBA8 = RGBA8::new(0x12, 0x34, 0x56, 0);
+
+    #[test]
+    fn merges_transparent_entries_into_the_key() {
+        let palette = [RED, CLEAR, BLUE, CLEAR.with_alpha(0)];
+        let key = RGB8::new(0, 0xFF, 0);
+        let (keyed, indices) = ColorKey::Rgb(key).apply(&palette, &[0, 1, 2, 3]).unwrap();
+        assert_eq!(keyed, [key.with_alpha(0), RED, BLUE]);
+        assert_eq!(indices, [1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn chooses_a_free_key() {
+        let palette = [RGBA8::new(0xFF, 0, 0xFF, 0xFF), CLEAR];
+        let (keyed, _) = ColorKey::Auto.apply(&palette, &[0, 1]).unwrap();
+        assert_eq!(keyed[0].a, 0);
+        assert_ne!(keyed[0].rgb(), palette[0].rgb());
+    }
+
+    #[test]
+    fn refuses_a_key_in_use() {
+        assert!(ColorKey::Rgb(RED.rgb())
+            .apply(&[RED, CLEAR], &[0, 1])
+            .is_err());
+    }
+
+    #[test]
+    fn refuses_partly_transparent_entries() {
+        let palette = [RED, RED.with_alpha(0x80), CLEAR];
+        assert!(ColorKey::Auto.apply(&palette, &[0, 1, 2]).is_err());
+    }
+}
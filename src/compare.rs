@@ -0,0 +1,103 @@
+use crate::source::Source;
+use anyhow::{bail, Result};
+
+pub struct Thresholds {
+    pub size_delta: f64,
+    pub pixel_diff: f64,
+}
+
+pub struct Comparison {
+    pub size: usize,
+    pub baseline_size: usize,
+    pub pixel_diff: f64,
+}
+
+impl Comparison {
+    /// Compare a candidate decoded from its encoded output with the baseline, in which fully
+    /// transparent pixels match whatever their color
+    pub fn new(
+        candidate: &Source,
+        size: usize,
+        baseline: &Source,
+        baseline_size: usize,
+    ) -> Result<Self> {
+        let (width, height) = (candidate.width, candidate.height);
+        if (baseline.width, baseline.height) != (width, height) {
+            bail!(
+                "baseline is {}×{} but source is {width}×{height}",
+                baseline.width,
+                baseline.height
+            );
+        }
+
+        let differing = (candidate.rgba.iter())
+            .zip(&baseline.rgba)
+            .filter(|(c, b)| c != b && !(c.a == 0 && b.a == 0))
+            .count();
+
+        #[allow(clippy::cast_precision_loss)]
+        let pixel_diff = differing as f64 / (width * height).max(1) as f64;
+
+        Ok(Self {
+            size,
+            baseline_size,
+            pixel_diff,
+        })
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn size_delta(&self) -> f64 {
+        (self.size as f64 - self.baseline_size as f64) / (self.baseline_size.max(1) as f64)
+    }
+
+    pub fn check(&self, thresholds: &Thresholds) -> Result<()> {
+        println!(
+            "size: {} bytes (baseline {} bytes, {:+.2}%)",
+            self.size,
+            self.baseline_size,
+            self.size_delta() * 100.0
+        );
+        println!("pixel difference: {:.2}%", self.pixel_diff * 100.0);
+
+        if self.size_delta() > thresholds.size_delta {
+            bail!(
+                "size regressed by more than {:.2}%",
+                thresholds.size_delta * 100.0
+            );
+        }
+        if self.pixel_diff > thresholds.pixel_diff {
+            bail!(
+                "pixel difference exceeds {:.2}%",
+                thresholds.pixel_diff * 100.0
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgb::RGBA8;
+
+    fn source(rgba: Vec<RGBA8>) -> Source {
+        Source {
+            uses_alpha: true,
+            bit_depth_reduced: false,
+            was_indexed: false,
+            width: rgba.len(),
+            height: 1,
+            rgba,
+            chunks: Vec::new(),
+            filter: None,
+        }
+    }
+
+    #[test]
+    fn matches_transparent_pixels_of_any_color() {
+        let candidate = source(vec![RGBA8::new(0, 0, 0, 0), RGBA8::new(9, 9, 9, 255)]);
+        let baseline = source(vec![RGBA8::new(255, 0, 0, 0), RGBA8::new(9, 9, 9, 254)]);
+        let comparison = Comparison::new(&candidate, 1, &baseline, 1).unwrap();
+        assert!((comparison.pixel_diff - 0.5).abs() < f64::EPSILON);
+    }
+}
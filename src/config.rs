@@ -0,0 +1,102 @@
+use crate::preview::Params;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// State kept between runs in the user’s configuration directory
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Parameters last exported for each source, by canonical path
+    params: BTreeMap<PathBuf, Params>,
+    /// Sources most recently opened, newest first
+    recent: Vec<PathBuf>,
+    /// Typical ratio of exported size to the quick estimate of it
+    calibration: Option<f64>,
+    /// Whether the file couldn’t be read, and so mustn’t be overwritten
+    #[serde(skip)]
+    unreadable: bool,
+}
+
+const RECENT: usize = 10;
+
+/// Weight of the latest export in the calibration
+const CALIBRATION_RATE: f64 = 0.5;
+
+impl Config {
+    fn path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .or_else(|| env::var_os("APPDATA"))
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("pngquant-interactive").join("config.json"))
+    }
+
+    /// Read the configuration, or start afresh if there is none
+    pub fn load() -> Result<Self> {
+        match Self::path() {
+            Some(path) if path.exists() => {
+                let json = fs::read_to_string(&path)?;
+                serde_json::from_str(&json).with_context(|| format!("{}", path.display()))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Start afresh without overwriting a configuration that couldn’t be read
+    pub fn unreadable() -> Self {
+        Self {
+            unreadable: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if self.unreadable {
+            return Ok(());
+        }
+        let path = Self::path().context("no configuration directory")?;
+        fs::create_dir_all(path.parent().expect("directory"))?;
+        Ok(fs::write(path, serde_json::to_string_pretty(self)? + "\n")?)
+    }
+
+    pub fn params(&self, source: &Path) -> Option<&Params> {
+        self.params.get(&source.canonicalize().ok()?)
+    }
+
+    /// Note a source as most recently opened, forgetting any that no longer exist
+    pub fn opened(&mut self, source: &Path) -> Result<()> {
+        let source = source.canonicalize()?;
+        self.recent.retain(|r| r != &source && r.exists());
+        self.recent.insert(0, source);
+        self.recent.truncate(RECENT);
+        Ok(())
+    }
+
+    pub fn recent(&self) -> Vec<PathBuf> {
+        self.recent.iter().filter(|r| r.exists()).cloned().collect()
+    }
+
+    /// Factor by which to scale quick estimates of size
+    pub fn calibration(&self) -> f64 {
+        self.calibration.unwrap_or(1.0)
+    }
+
+    /// Learn from the actual size of an export how far its quick estimate was off
+    pub fn calibrate(&mut self, estimated: usize, actual: usize) {
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = actual as f64 / estimated.max(1) as f64;
+        let calibrated = self.calibration.map_or(ratio, |previous| {
+            (ratio - previous).mul_add(CALIBRATION_RATE, previous)
+        });
+        self.calibration.replace(calibrated);
+    }
+
+    pub fn remember(&mut self, source: &Path, params: Params) -> Result<()> {
+        self.params.insert(source.canonicalize()?, params);
+        Ok(())
+    }
+}
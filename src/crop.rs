@@ -0,0 +1,94 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Rectangle within an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Crop {
+    /// Rectangle spanning two corners, inclusive
+    pub fn between((x0, y0): (usize, usize), (x1, y1): (usize, usize)) -> Self {
+        Self {
+            x: x0.min(x1),
+            y: y0.min(y1),
+            width: x0.abs_diff(x1) + 1,
+            height: y0.abs_diff(y1) + 1,
+        }
+    }
+
+    /// Whether the rectangle lies within an image of the given size, which one too far out to
+    /// measure doesn’t
+    pub const fn fits(&self, width: usize, height: usize) -> bool {
+        match (
+            self.x.checked_add(self.width),
+            self.y.checked_add(self.height),
+        ) {
+            (Some(right), Some(bottom)) => right <= width && bottom <= height,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Crop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}×{} at {},{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<usize> = s
+            .split(',')
+            .map(|v| v.trim().parse::<usize>().map_err(|e| format!("{e}")))
+            .collect::<Result<_, _>>()?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            [_, _, _, _] => Err(String::from("width and height must not be zero")),
+            _ => Err(String::from("expected X,Y,WIDTH,HEIGHT")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_within_the_image() {
+        let crop = Crop {
+            x: 2,
+            y: 3,
+            width: 4,
+            height: 5,
+        };
+        assert!(crop.fits(6, 8));
+        assert!(!crop.fits(5, 8));
+        assert!(!crop.fits(6, 7));
+    }
+
+    #[test]
+    fn fits_nowhere_too_far_out_to_measure() {
+        for offset in [u32::MAX as usize, usize::MAX] {
+            let crop = Crop {
+                x: offset,
+                y: offset,
+                width: usize::MAX,
+                height: 1,
+            };
+            assert!(!crop.fits(usize::MAX, usize::MAX));
+            assert!(!Crop { x: 1, ..crop }.fits(usize::MAX, usize::MAX));
+        }
+    }
+}
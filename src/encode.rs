@@ -7,12 +7,18 @@ pub trait Encode {
     fn encode<W: Write>(&self, priority: Priority, into: W) -> Result<()>;
 
     fn estimate(&self) -> Result<usize> {
+        self.measure(Priority::Speed)
+    }
+
+    /// Size of the encoding at the given priority, without keeping it
+    fn measure(&self, priority: Priority) -> Result<usize> {
         let mut sink = CountingSink::default();
-        self.encode(Priority::Speed, &mut sink)?;
+        self.encode(priority, &mut sink)?;
         Ok(sink.len())
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Priority {
     Size,
     Speed,
@@ -0,0 +1,191 @@
+use crate::encode::Encode;
+use crate::preview::Params;
+use crate::source::Source;
+use crate::template::NameTemplate;
+use crate::{debug, info};
+use anyhow::{bail, Result};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Where and how exported files are written
+#[derive(Clone, Debug)]
+pub struct Exporter {
+    /// Output file or directory, or none to write beside the source
+    pub output: Option<PathBuf>,
+    pub suffix: Option<String>,
+    pub name_template: NameTemplate,
+    /// Whether exports recompress the source without reducing colors
+    pub lossless: bool,
+    /// Whether one run exports several files
+    pub multiple: bool,
+    pub force: bool,
+    pub in_place: bool,
+    /// Copy the source file instead when the export would be larger
+    pub keep_smaller: bool,
+    /// Losslessly optimize exports further with oxipng
+    #[cfg(feature = "oxipng")]
+    pub optimize: bool,
+    /// Whether the palette's size or order is fixed and must survive optimization
+    #[cfg(feature = "oxipng")]
+    pub fixed_palette: bool,
+}
+
+impl Exporter {
+    /// Path to export a source to with the given parameters
+    pub fn path(&self, source: &Path, params: &Params) -> Result<PathBuf> {
+        let stem = if is_stdin(source) {
+            OsStr::new("stdin")
+        } else {
+            source.file_stem().expect("file")
+        };
+        let suffix = match self.suffix {
+            Some(ref s) => s,
+            None if self.lossless => "-opt",
+            None if params.dithering == 0 => "-or8",
+            None => "-fs8",
+        };
+        let derived = source.with_file_name(self.name_template.expand(stem, suffix, params));
+        let output = match self.output {
+            Some(ref dir) if dir.is_dir() => dir.join(derived.file_name().expect("file")),
+            Some(_) if self.multiple => {
+                bail!("--output must be an existing directory for multiple outputs");
+            }
+            Some(ref file) => match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                    bail!("output directory {} does not exist", dir.display());
+                }
+                _ => file.clone(),
+            },
+            None => derived,
+        };
+        if is_same_file(&output, source) && !self.in_place {
+            bail!(
+                "refusing to overwrite {} without --in-place",
+                source.display()
+            );
+        }
+        Ok(output)
+    }
+
+    pub fn may_write(&self, source: &Path, output: &Path) -> bool {
+        self.force || !output.exists() || (self.in_place && is_same_file(output, source))
+    }
+
+    /// Exported data after any lossless optimization, reporting its effect
+    #[cfg_attr(
+        not(feature = "oxipng"),
+        allow(
+            clippy::missing_const_for_fn,
+            clippy::unnecessary_wraps,
+            clippy::unused_self
+        )
+    )]
+    pub fn optimize<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "oxipng")]
+        if self.optimize {
+            let mut options = oxipng::Options::from_preset(2);
+            // Keep the palette as laid out when its size or order is fixed
+            if self.fixed_palette {
+                options.palette_reduction = false;
+                options.bit_depth_reduction = false;
+                options.color_type_reduction = false;
+            }
+            let optimized = oxipng::optimize_from_memory(data, &options)?;
+            info!("Optimized {} → {} bytes", data.len(), optimized.len());
+            return Ok(Cow::Owned(optimized));
+        }
+        Ok(Cow::Borrowed(data))
+    }
+
+    /// Write exported data, or with --keep-smaller the source if that's smaller, returning the size written
+    pub fn write(&self, source: &Path, output: &Path, data: &[u8]) -> Result<usize> {
+        if self.keep_smaller && !is_stdin(source) {
+            let original = usize::try_from(fs::metadata(source)?.len())?;
+            if original < data.len() {
+                if !is_same_file(output, source) {
+                    fs::copy(source, output)?;
+                }
+                info!(
+                    "Kept original {} of {original} bytes instead of {} bytes",
+                    output.display(),
+                    data.len()
+                );
+                return Ok(original);
+            }
+        }
+        fs::write(output, data)?;
+        debug!("Wrote {} bytes to {}", data.len(), output.display());
+        Ok(data.len())
+    }
+}
+
+/// Whether a path stands for standard input or output
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Whether both paths lead to one file, even if spelled differently
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Contents of a file, or of standard input
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if is_stdin(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        Ok(fs::read(path)?)
+    }
+}
+
+/// Size of the source file, or an estimate of it when read from standard input
+pub fn original_size(path: &Path, source: &Source) -> Result<usize> {
+    if is_stdin(path) {
+        source.estimate()
+    } else {
+        Ok(usize::try_from(fs::metadata(path)?.len())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn keeps_original_written_to_itself_by_another_name() {
+        let dir = env::temp_dir().join(format!("pngquant-interactive-{}", process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let source = dir.join("a.png");
+        fs::write(&source, b"original").unwrap();
+        let exporter = Exporter {
+            output: None,
+            suffix: None,
+            name_template: "{stem}{suffix}.png".parse::<NameTemplate>().unwrap(),
+            lossless: false,
+            multiple: false,
+            force: true,
+            in_place: true,
+            keep_smaller: true,
+            #[cfg(feature = "oxipng")]
+            optimize: false,
+            #[cfg(feature = "oxipng")]
+            fixed_palette: false,
+        };
+        let output = dir.join("nested").join("..").join("a.png");
+        let written = exporter.write(&source, &output, b"larger export").unwrap();
+        let kept = fs::read(&source).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(kept, b"original");
+    }
+}
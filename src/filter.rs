@@ -0,0 +1,105 @@
+use png::{AdaptiveFilterType, Encoder, FilterType};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Filter applied to each row before compression
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Whichever of the above suits each row
+    Adaptive,
+}
+
+impl Filter {
+    pub fn apply<W: Write>(self, encoder: &mut Encoder<W>) {
+        let (filter, adaptive) = match self {
+            Self::None => (FilterType::NoFilter, false),
+            Self::Sub => (FilterType::Sub, false),
+            Self::Up => (FilterType::Up, false),
+            Self::Avg => (FilterType::Avg, false),
+            Self::Paeth => (FilterType::Paeth, false),
+            Self::Adaptive => (FilterType::default(), true),
+        };
+        encoder.set_filter(filter);
+        encoder.set_adaptive_filter(if adaptive {
+            AdaptiveFilterType::Adaptive
+        } else {
+            AdaptiveFilterType::NonAdaptive
+        });
+    }
+
+    /// Filter a row of packed samples against the row above it, if any, prefixed by the filter
+    /// type as stored in the image data
+    pub fn filter_row(self, row: &[u8], above: Option<&[u8]>, bytes_per_pixel: usize) -> Vec<u8> {
+        let kinds: &[u8] = match self {
+            Self::None => &[0],
+            Self::Sub => &[1],
+            Self::Up => &[2],
+            Self::Avg => &[3],
+            Self::Paeth => &[4],
+            Self::Adaptive => &[0, 1, 2, 3, 4],
+        };
+        // Choose as libpng does, by the least sum of differences taken as signed
+        kinds
+            .iter()
+            .map(|&kind| filter_with(kind, row, above, bytes_per_pixel))
+            .min_by_key(|filtered| {
+                (filtered[1..].iter())
+                    .map(|&d| u64::from(i8::from_ne_bytes([d]).unsigned_abs()))
+                    .sum::<u64>()
+            })
+            .expect("filter")
+    }
+}
+
+fn filter_with(kind: u8, row: &[u8], above: Option<&[u8]>, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity(1 + row.len());
+    filtered.push(kind);
+    for (i, &x) in row.iter().enumerate() {
+        let left = |row: &[u8]| i.checked_sub(bytes_per_pixel).map_or(0, |j| row[j]);
+        let (a, b, c) = (left(row), above.map_or(0, |r| r[i]), above.map_or(0, left));
+        let predicted = match kind {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => a.midpoint(b),
+            _ => paeth(a, b, c),
+        };
+        filtered.push(x.wrapping_sub(predicted));
+    }
+    filtered
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let distance = |n: u8| (p - i16::from(n)).abs();
+    if distance(a) <= distance(b) && distance(a) <= distance(c) {
+        a
+    } else if distance(b) <= distance(c) {
+        b
+    } else {
+        c
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "sub" => Ok(Self::Sub),
+            "up" => Ok(Self::Up),
+            "avg" => Ok(Self::Avg),
+            "paeth" => Ok(Self::Paeth),
+            "adaptive" => Ok(Self::Adaptive),
+            _ => Err(String::from(
+                "expected none, sub, up, avg, paeth, or adaptive",
+            )),
+        }
+    }
+}
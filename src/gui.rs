@@ -0,0 +1,871 @@
+use crate::args::{Args, DEFAULTS};
+use crate::loupe;
+use crate::viewport::{Channel, Mode, Viewport};
+use crate::worker::{Action, Event, Worker};
+use anyhow::Result;
+use fltk::app::{self, App, MouseButton, MouseWheel, Scheme};
+use fltk::button::Button;
+use fltk::dialog::{self, ColorMode, NativeFileChooser, NativeFileChooserType};
+use fltk::enums::{Align, CallbackTrigger, Color, Event as UiEvent, FrameType, Key, Shortcut};
+use fltk::frame::Frame;
+use fltk::input::IntInput;
+use fltk::menu::{MenuButton, MenuFlag};
+use fltk::misc::Progress;
+use fltk::prelude::*;
+use fltk::valuator::HorSlider;
+use fltk::window::Window;
+use fltk_theme::{color_themes, ColorTheme};
+use pngquant_interactive::attributes::AdvancedAttributes;
+use pngquant_interactive::config::Config;
+use pngquant_interactive::crop::Crop;
+use pngquant_interactive::encode::{Encode, Priority};
+use pngquant_interactive::export::{is_stdin, Exporter};
+use pngquant_interactive::history::History;
+use pngquant_interactive::palette;
+use pngquant_interactive::preview::{Params, Preview, UNPAINTED_IMPORTANCE};
+use pngquant_interactive::utilities::{
+    format_change, format_rgba, int_from_f64, parse_dropped, parse_rgba,
+};
+use rgb::RGB8;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+
+/// Space between and around widgets
+pub const MARGIN: i32 = 8;
+
+/// Seconds to gather slider changes before previewing them
+const SETTLING_TIME: f64 = 0.05;
+
+/// Custom event telling the window that another source was opened
+const OPENED: UiEvent = UiEvent::from_i32(100);
+
+/// Show the preview in a window for adjusting its parameters until it's exported or closed
+pub fn run(
+    args: Args,
+    exporter: Exporter,
+    attributes: AdvancedAttributes,
+    mut config: Config,
+    preview: Preview,
+    params: Params,
+) -> Result<()> {
+    let params = Arc::new(RwLock::new(params));
+    let (to_app, for_app) = app::channel();
+    let (to_worker, for_worker) = mpsc::channel();
+
+    if !is_stdin(args.path()) {
+        config.opened(args.path())?;
+        if let Err(e) = config.save() {
+            eprintln!("Not remembering recent files: {e}");
+        }
+    }
+
+    // Build GUI
+    let (c, m, lh, gh, ph, sh, th, iw) = (12, MARGIN, 20, 12, 48, 24, 16, 36);
+    let (ww_min, wh_min) = (600, m + ph + m + gh + m + sh + lh + th + th + m);
+    let (vw, vh) = (
+        (i32::try_from(preview.source.width)?).max(ww_min),
+        i32::try_from(preview.source.height)?,
+    );
+    let (wh, cw) = (
+        vh + m + ph + m + gh + m + sh + lh + th + th + m,
+        (vw - m) / c,
+    );
+    let app = App::default().with_scheme(Scheme::Gtk);
+    ColorTheme::new(color_themes::DARK_THEME).apply();
+    let mut window = Window::default()
+        .with_size(vw, wh)
+        .with_label(&title(args.path()));
+    let source_size = Rc::new(Cell::new((preview.source.width, preview.source.height)));
+    let mut view = Frame::default().with_pos(0, 0).with_size(vw, vh);
+    view.set_frame(FrameType::FlatBox); // Clear remnants of a larger image
+    let spinner = Frame::default()
+        .with_pos(0, 0)
+        .with_size(vw, vh)
+        .with_label("@refresh");
+    let mut histogram = Frame::default()
+        .with_pos(m, vh + m)
+        .with_size(vw - m * 3 - cw * 4, ph);
+    histogram.set_frame(FrameType::FlatBox);
+    let mut swatches = Frame::default()
+        .with_pos(vw - m - cw * 4, vh + m)
+        .with_size(cw * 4, ph)
+        .with_align(Align::Inside | Align::TopLeft);
+    swatches.set_frame(FrameType::FlatBox);
+    let mut gauge = Progress::default()
+        .with_pos(m, vh + m + ph + m)
+        .with_size(vw - m * 3 - cw * 2, gh);
+    gauge.set_selection_color(Color::Foreground);
+    gauge.set_minimum(0.0);
+    gauge.set_maximum(1.0);
+    gauge.set_value(0.0);
+    gauge.set_label_size(10);
+    let mut error_label = Frame::default()
+        .with_pos(vw - m - cw * 2, vh + m + ph + m)
+        .with_size(cw * 2, gh)
+        .with_align(Align::Left | Align::Inside);
+    error_label.set_label_size(10);
+    let settling = Rc::new(Cell::new(false));
+    let history = Rc::new(RefCell::new(History::new(
+        params.read().expect("params").clone(),
+    )));
+    macro_rules! slider {
+        ($l:expr, $param:ident, $min:expr, $max:expr, $c0:expr, $c1:expr) => {{
+            let (x, y, w) = (
+                cw * $c0 + m,
+                vh + m + ph + m + gh + m,
+                cw * $c1 - cw * $c0 - m,
+            );
+            let mut input = IntInput::default().with_pos(x, y).with_size(iw, sh);
+            let mut slider = HorSlider::default()
+                .with_pos(x + iw, y)
+                .with_size(w - iw, sh)
+                .with_label($l);
+            slider.set_minimum($min.into());
+            slider.set_maximum($max.into());
+            slider.set_step(1.0, 1);
+            let value = params.read().expect("params").$param;
+            slider.set_value(value.into());
+            input.set_value(&value.to_string());
+
+            // Coalesce a burst of changes into one preview of the latest parameters
+            let schedule = {
+                let (to_worker, settling) = (to_worker.clone(), settling.clone());
+                move || {
+                    if !settling.replace(true) {
+                        let (to_worker, settling) = (to_worker.clone(), settling.clone());
+                        app::add_timeout3(SETTLING_TIME, move |_| {
+                            settling.set(false);
+                            to_worker.send(Action::Preview).expect("worker");
+                        });
+                    }
+                }
+            };
+            slider.set_callback({
+                let (params, mut input, schedule) =
+                    (params.clone(), input.clone(), schedule.clone());
+                move |s| {
+                    let value = int_from_f64(s.value());
+                    params.write().expect("params").$param = value;
+                    input.set_value(&value.to_string());
+                    schedule();
+                }
+            });
+            input.set_trigger(CallbackTrigger::EnterKey | CallbackTrigger::Release);
+            input.set_callback({
+                let (params, history, mut slider) =
+                    (params.clone(), history.clone(), slider.clone());
+                move |i| {
+                    let value = i
+                        .value()
+                        .parse()
+                        .map_or(slider.value(), |v| slider.clamp(v));
+                    slider.set_value(value);
+                    let value = int_from_f64(value);
+                    params.write().expect("params").$param = value;
+                    i.set_value(&value.to_string());
+                    history
+                        .borrow_mut()
+                        .commit(params.read().expect("params").clone());
+                    schedule();
+                }
+            });
+            (slider, input)
+        }};
+    }
+    let controls = [
+        slider!("Effort", effort, 1, 10, 0, 2),
+        slider!("Color Preservation", preservation, 0, 100, 2, 5),
+        slider!("Colors", colors, 2, 256, 5, 7),
+        slider!("Dithering", dithering, 0, 10, 7, 9),
+        slider!("Posterization", posterization, 0, 4, 9, 11),
+    ];
+    let mut sliders = controls.clone().map(|(slider, _)| slider);
+    let mut inputs = controls.map(|(_, input)| input);
+    if args.lossless {
+        sliders.iter_mut().for_each(WidgetExt::deactivate);
+        inputs.iter_mut().for_each(WidgetExt::deactivate);
+    } else {
+        sliders[1].take_focus()?;
+    }
+    for slider in &mut sliders {
+        // Commit once per gesture rather than for every intermediate value
+        let (history, params) = (history.clone(), params.clone());
+        slider.handle(move |_, event| {
+            if matches!(event, UiEvent::Released | UiEvent::KeyUp) {
+                let params = params.read().expect("params").clone();
+                history.borrow_mut().commit(params);
+            }
+            false
+        });
+    }
+    let mut ok_button = Button::default()
+        .with_pos(cw * 11 + m, vh + m + ph + m + gh + m)
+        .with_size(cw * 12 - cw * 11 - m, sh + lh)
+        .with_label("OK");
+    ok_button.set_callback({
+        let to_worker = to_worker.clone();
+        move |b| {
+            b.window().expect("window").deactivate();
+            to_worker
+                .send(Action::Export { overwrite: false })
+                .expect("worker");
+        }
+    });
+    let mut status_bar = Frame::default()
+        .with_pos(m, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(vw - m * 8 - th - cw * 6, th)
+        .with_align(Align::Left | Align::Inside);
+    status_bar.set_label_size(12);
+    let mut details_bar = Frame::default()
+        .with_pos(m, vh + m + ph + m + gh + m + sh + lh + th)
+        .with_size(vw - m * 2, th)
+        .with_align(Align::Left | Align::Inside);
+    details_bar.set_label_size(12);
+    let strength = Rc::new(Cell::new(u8::MAX));
+    let mut brush_menu = MenuButton::default()
+        .with_pos(vw - m * 6 - th - cw * 6, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(cw, th)
+        .with_label("Brush");
+    brush_menu.set_label_size(12);
+    brush_menu.set_tooltip("Paint what matters most to preserve ([ and ] resize the brush)");
+    if args.lossless {
+        brush_menu.deactivate();
+    }
+    for (label, value) in [
+        ("Strength/Full", u8::MAX),
+        ("Strength/Three quarters", u8::MAX / 4 * 3),
+        ("Strength/Half", u8::MAX / 2),
+        ("Strength/Erase", UNPAINTED_IMPORTANCE),
+    ] {
+        let flag = if value == strength.get() {
+            MenuFlag::Radio | MenuFlag::Value
+        } else {
+            MenuFlag::Radio
+        };
+        let strength = strength.clone();
+        brush_menu.add(label, Shortcut::None, flag, move |_| strength.set(value));
+    }
+    brush_menu.add("Clear (Delete)", Shortcut::None, MenuFlag::Normal, {
+        let to_worker = to_worker.clone();
+        move |_| to_worker.send(Action::ClearImportance).expect("worker")
+    });
+    let mut copy_button = Button::default()
+        .with_pos(vw - m * 5 - th - cw * 5, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(cw, th)
+        .with_label("Copy");
+    copy_button.set_label_size(12);
+    copy_button.set_tooltip("Copy the preview to the clipboard as PNG (Ctrl+C)");
+    copy_button.set_callback({
+        let to_worker = to_worker.clone();
+        move |_| to_worker.send(Action::Copy).expect("worker")
+    });
+    let mut reset_button = Button::default()
+        .with_pos(vw - m * 4 - th - cw * 4, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(cw, th)
+        .with_label("Reset");
+    reset_button.set_label_size(12);
+    reset_button.set_tooltip("Reset parameters to defaults");
+    if args.lossless {
+        reset_button.deactivate();
+    }
+    reset_button.set_callback({
+        let (to_worker, params) = (to_worker.clone(), params.clone());
+        let (history, mut sliders, mut inputs) = (history.clone(), sliders.clone(), inputs.clone());
+        move |_| {
+            show_params(&mut sliders, &mut inputs, &DEFAULTS);
+            *params.write().expect("params") = DEFAULTS;
+            history.borrow_mut().commit(DEFAULTS);
+            to_worker.send(Action::Preview).expect("worker");
+        }
+    });
+    let mut recent = config.recent();
+    let mut recent_menu = MenuButton::default()
+        .with_pos(vw - m * 3 - th - cw * 3, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(cw * 3 / 2, th)
+        .with_label("Recent");
+    recent_menu.set_label_size(12);
+    fill_recent(&mut recent_menu, &recent, &to_worker);
+    let mut preset_menu = MenuButton::default()
+        .with_pos(
+            vw - m * 2 - th - cw * 3 / 2,
+            vh + m + ph + m + gh + m + sh + lh,
+        )
+        .with_size(cw * 3 / 2, th)
+        .with_label("Preset");
+    preset_menu.set_label_size(12);
+    preset_menu.add("Load…", Shortcut::None, MenuFlag::Normal, {
+        let (to_worker, params) = (to_worker.clone(), params.clone());
+        let (history, mut sliders, mut inputs) = (history.clone(), sliders.clone(), inputs.clone());
+        move |_| {
+            let Some(path) = dialog::file_chooser("Load preset", "*.json", ".", false) else {
+                return;
+            };
+            match Params::load(Path::new(&path)) {
+                Ok(preset) => {
+                    show_params(&mut sliders, &mut inputs, &preset);
+                    *params.write().expect("params") = preset.clone();
+                    history.borrow_mut().commit(preset);
+                    to_worker.send(Action::Preview).expect("worker");
+                }
+                Err(e) => dialog::alert_default(&format!("{e}")),
+            }
+        }
+    });
+    preset_menu.add("Save…", Shortcut::None, MenuFlag::Normal, {
+        let params = params.clone();
+        move |_| {
+            let Some(path) = dialog::file_chooser("Save preset", "*.json", ".", false) else {
+                return;
+            };
+            let preset = params.read().expect("params").clone();
+            if let Err(e) = preset.save(Path::new(&path)) {
+                dialog::alert_default(&format!("{e}"));
+            }
+        }
+    });
+    let mut swatch = Button::default()
+        .with_pos(vw - m - th, vh + m + ph + m + gh + m + sh + lh)
+        .with_size(th, th);
+    swatch.set_tooltip("Background");
+    if let Some(color) = args.background {
+        swatch.set_color(Color::from_rgb(color.r, color.g, color.b));
+    }
+    swatch.set_callback({
+        let to_worker = to_worker.clone();
+        move |s| {
+            let current = s.color().to_rgb();
+            let chosen = dialog::color_chooser_with_default("Background", ColorMode::Rgb, current);
+            s.set_color(Color::from_rgb(chosen.0, chosen.1, chosen.2));
+            s.redraw();
+            let color = RGB8::from(chosen);
+            to_worker.send(Action::Background(color)).expect("worker");
+        }
+    });
+    let loupe_size = i32::try_from(loupe::SIZE)?;
+    let mut loupe = Frame::default()
+        .with_size(loupe_size, loupe_size + th)
+        .with_align(Align::Top | Align::Inside);
+    loupe.set_frame(FrameType::FlatBox);
+    loupe.set_label_size(12);
+    loupe.hide();
+    let mut band = Frame::default();
+    band.set_frame(FrameType::BorderFrame);
+    band.set_color(Color::Selection);
+    band.hide();
+    window.resizable(&view);
+    window.size_range(ww_min, wh_min, 0, 0);
+    window.handle({
+        let to_worker = to_worker.clone();
+        let (view, source_size) = (view.clone(), source_size.clone());
+        let lossless = args.lossless;
+        let (params, mut sliders, mut inputs) = (params.clone(), sliders.clone(), inputs.clone());
+        let mut viewport = Viewport::default();
+        let mut holding = false;
+        let mut panning = None;
+        let mut cropping = None;
+        let mut inspecting = false;
+        let mut drafting = args.draft;
+        let mut from_crop = false;
+        let mut hovered = None;
+        let swatches = swatches.clone();
+        let mut radius = 8;
+        move |_, event| match event {
+            UiEvent::KeyDown if app::event_key() == Key::Enter => {
+                ok_button.do_callback();
+                true
+            }
+            event if event == OPENED => {
+                viewport.crop = None;
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('d') => {
+                if !lossless && !holding {
+                    viewport.mode = viewport.mode.next();
+                    to_worker.send(Action::View(viewport)).expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyDown
+                if !app::is_event_ctrl() && channel_for(app::event_key()).is_some() =>
+            {
+                let channel = channel_for(app::event_key());
+                viewport.channel = if viewport.channel == channel {
+                    None
+                } else {
+                    channel
+                };
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('1') => {
+                viewport.actual = !viewport.actual;
+                viewport.zoom = 1.0;
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('s') => {
+                viewport.smooth = !viewport.smooth;
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('t') => {
+                viewport.backdrop = viewport.backdrop.next();
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char(' ') => {
+                if !lossless && !holding {
+                    holding = true;
+                    let original = Viewport {
+                        mode: Mode::Original,
+                        ..viewport
+                    };
+                    to_worker.send(Action::View(original)).expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyUp if app::event_key() == Key::from_char(' ') => {
+                if holding {
+                    holding = false;
+                    to_worker.send(Action::View(viewport)).expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('f') => {
+                if !lossless {
+                    drafting = !drafting;
+                    to_worker.send(Action::Draft(drafting)).expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('p') => {
+                if !lossless {
+                    from_crop = !from_crop;
+                    to_worker
+                        .send(Action::PaletteFromCrop(from_crop))
+                        .expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('l') => {
+                inspecting = !inspecting;
+                if !inspecting {
+                    to_worker.send(Action::Inspect(None)).expect("worker");
+                }
+                true
+            }
+            UiEvent::Move => {
+                let (x, y) = app::event_coords();
+                let swatch = match (
+                    usize::try_from(x - swatches.x()),
+                    usize::try_from(y - swatches.y()),
+                ) {
+                    (Ok(sx), Ok(sy)) => {
+                        #[allow(clippy::cast_sign_loss)]
+                        let size = (swatches.width() as usize, swatches.height() as usize);
+                        palette::swatch_at((sx, sy), size.0, size.1)
+                    }
+                    _ => None,
+                };
+                if swatch != hovered {
+                    hovered = swatch;
+                    to_worker.send(Action::Hover(swatch)).expect("worker");
+                }
+                if inspecting {
+                    let pixel = locate(&view, source_size.get(), &viewport, (x, y));
+                    let inspected = pixel.map(|p| (p, (x, y)));
+                    to_worker.send(Action::Inspect(inspected)).expect("worker");
+                }
+                false
+            }
+            UiEvent::KeyDown if app::is_event_ctrl() && app::event_key() == Key::from_char('c') => {
+                to_worker.send(Action::Copy).expect("worker");
+                true
+            }
+            UiEvent::KeyDown
+                if app::is_event_ctrl()
+                    && [Key::from_char('z'), Key::from_char('y')].contains(&app::event_key()) =>
+            {
+                let mut history = history.borrow_mut();
+                let restored = if app::event_key() == Key::from_char('z') {
+                    history.undo()
+                } else {
+                    history.redo()
+                };
+                if let Some(restored) = restored {
+                    show_params(&mut sliders, &mut inputs, restored);
+                    *params.write().expect("params") = restored.clone();
+                    to_worker.send(Action::Preview).expect("worker");
+                }
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char('[') => {
+                radius = (radius / 2).max(1);
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::from_char(']') => {
+                radius = (radius * 2).min(256);
+                true
+            }
+            UiEvent::KeyDown if app::event_key() == Key::Delete => {
+                to_worker.send(Action::ClearImportance).expect("worker");
+                true
+            }
+            UiEvent::Push if app::event_mouse_button() == MouseButton::Middle => {
+                panning.replace(app::event_coords());
+                true
+            }
+            UiEvent::Drag if app::event_mouse_button() == MouseButton::Middle => {
+                let (x, y) = app::event_coords();
+                let Some((px, py)) = panning.replace((x, y)) else {
+                    return false;
+                };
+                let (sw, sh) = source_size.get();
+                #[allow(clippy::cast_sign_loss)]
+                let view_size = (view.width() as usize, view.height() as usize);
+                let distance = (f64::from(x - px), f64::from(y - py));
+                viewport.pan_by(distance, viewport.extent(sw, sh), view_size);
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::Released if app::event_mouse_button() == MouseButton::Middle => {
+                panning.take();
+                true
+            }
+            UiEvent::Push
+                if app::event_mouse_button() == MouseButton::Left && hovered.is_some() =>
+            {
+                let index = hovered.expect("swatch");
+                to_worker.send(Action::Select(index)).expect("worker");
+                true
+            }
+            UiEvent::Push
+                if app::event_mouse_button() == MouseButton::Right && hovered.is_some() =>
+            {
+                let index = hovered.expect("swatch");
+                to_worker.send(Action::Lock(index)).expect("worker");
+                true
+            }
+            UiEvent::Push if app::event_mouse_button() == MouseButton::Right => {
+                let start = app::event_coords();
+                let pixel = locate(&view, source_size.get(), &viewport, start);
+                cropping = pixel.map(|p| (start, p));
+                band.resize(start.0, start.1, 0, 0);
+                cropping.is_some()
+            }
+            UiEvent::Drag if app::event_mouse_button() == MouseButton::Right => {
+                let Some(((x0, y0), _)) = cropping else {
+                    return false;
+                };
+                let (x1, y1) = app::event_coords();
+                let (x1, y1) = (
+                    x1.clamp(view.x(), view.x() + view.w() - 1),
+                    y1.clamp(view.y(), view.y() + view.h() - 1),
+                );
+                band.resize(x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs());
+                band.show();
+                band.window().expect("window").redraw();
+                true
+            }
+            UiEvent::Released if app::event_mouse_button() == MouseButton::Right => {
+                let Some((start, corner)) = cropping.take() else {
+                    return false;
+                };
+                let end = app::event_coords();
+                band.hide();
+                band.window().expect("window").redraw();
+
+                // Drawing a rectangle crops to it, and a click clears the crop
+                viewport.crop = if start == end {
+                    None
+                } else {
+                    let (x, y) = (
+                        end.0.clamp(view.x(), view.x() + view.w() - 1),
+                        end.1.clamp(view.y(), view.y() + view.h() - 1),
+                    );
+                    locate(&view, source_size.get(), &viewport, (x, y))
+                        .map(|other| Crop::between(corner, other))
+                };
+                viewport.zoom = 1.0;
+                viewport.center = (0.5, 0.5);
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::Push | UiEvent::Drag
+                if app::event_mouse_button() == MouseButton::Left
+                    && matches!(viewport.mode, Mode::Split) =>
+            {
+                match locate(&view, source_size.get(), &viewport, app::event_coords()) {
+                    Some((x, _)) => {
+                        let (sw, _) = source_size.get();
+                        let (x0, w) = viewport.crop.map_or((0, sw), |c| (c.x, c.width));
+                        #[allow(clippy::cast_precision_loss)]
+                        let divider = (x - x0) as f64 / w as f64;
+                        viewport.divider = divider;
+                        to_worker.send(Action::View(viewport)).expect("worker");
+                        true
+                    }
+                    None => false,
+                }
+            }
+            UiEvent::Push | UiEvent::Drag if app::event_mouse_button() == MouseButton::Left => {
+                match locate(&view, source_size.get(), &viewport, app::event_coords()) {
+                    Some((x, y)) => {
+                        (to_worker.send(Action::Paint(x, y, radius, strength.get())))
+                            .expect("worker");
+                        true
+                    }
+                    None => false,
+                }
+            }
+            UiEvent::Released if app::event_mouse_button() == MouseButton::Left => {
+                to_worker.send(Action::Preview).expect("worker");
+                false
+            }
+            UiEvent::MouseWheel => {
+                let factor = match app::event_dy() {
+                    MouseWheel::Up => 1.25,
+                    MouseWheel::Down => 0.8,
+                    _ => return false,
+                };
+                let (sw, sh) = source_size.get();
+                let extent = viewport.extent(sw, sh);
+                #[allow(clippy::cast_sign_loss)]
+                let view_size = (view.width() as usize, view.height() as usize);
+                let (x, y) = app::event_coords();
+                let point = (f64::from(x - view.x()), f64::from(y - view.y()));
+                let Some(at) = viewport.locate(extent, view_size, point) else {
+                    return false;
+                };
+                viewport.zoom_by(factor, extent, at);
+                to_worker.send(Action::View(viewport)).expect("worker");
+                true
+            }
+            UiEvent::DndEnter | UiEvent::DndDrag | UiEvent::DndRelease => true,
+            UiEvent::Paste => {
+                let text = app::event_text();
+                let Some(dropped) = text.lines().find_map(parse_dropped) else {
+                    return false;
+                };
+                to_worker.send(Action::Open(dropped)).expect("worker");
+                true
+            }
+            UiEvent::Resize => {
+                to_worker.send(Action::Resize).expect("worker");
+                false
+            }
+            _ => false,
+        }
+    });
+    window.end();
+    window.show();
+
+    // Start estimator
+    let priority = args.estimate_priority();
+    let calibration = match priority {
+        Priority::Speed => config.calibration(),
+        Priority::Size => 1.0,
+    };
+    let to_estimator = spawn_estimator(
+        params.clone(),
+        gauge.clone(),
+        to_app.clone(),
+        priority,
+        calibration,
+    );
+
+    // Start worker
+    let worker = Worker {
+        args,
+        exporter,
+        attributes,
+        config,
+        preview,
+        params,
+        view,
+        spinner,
+        histogram,
+        swatches,
+        gauge,
+        error_label,
+        status_bar,
+        details_bar,
+        loupe,
+        to_app,
+        to_self: to_worker.clone(),
+        to_estimator,
+    };
+    thread::spawn(move || worker.run(&for_worker));
+
+    // Run
+    to_worker.send(Action::Preview)?;
+    while app.wait() {
+        if let Some(event) = for_app.recv() {
+            match event {
+                Event::ConfirmOverwrite(path) => {
+                    let message = format!("{} already exists.", path.display());
+                    match dialog::choice2_default(&message, "Cancel", "Overwrite", "") {
+                        Some(1) => to_worker.send(Action::Export { overwrite: true })?,
+                        _ => window.activate(),
+                    }
+                }
+                Event::Edit(index, color) => {
+                    let prompt = format!("Entry {index} (RRGGBBAA)");
+                    if let Some(input) = dialog::input_default(&prompt, &format_rgba(color)) {
+                        match parse_rgba(&input) {
+                            Ok(color) => to_worker.send(Action::Recolor(index, color))?,
+                            Err(e) => dialog::alert_default(&e),
+                        }
+                    }
+                }
+                Event::Error(message) => {
+                    dialog::alert_default(&message);
+                    window.activate();
+                }
+                Event::Exported(size) => {
+                    println!("Exported {size} bytes");
+                    app.quit();
+                }
+                Event::Opened(path, (width, height)) => {
+                    let opened = path.canonicalize().unwrap_or_else(|_| path.clone());
+                    recent.retain(|r| r != &opened);
+                    recent.insert(0, opened);
+                    fill_recent(&mut recent_menu, &recent, &to_worker);
+                    source_size.set((width, height));
+                    window.set_label(&title(&path));
+                    app::handle_main(OPENED)?;
+                    let (vw, vh) = (i32::try_from(width)?.max(ww_min), i32::try_from(height)?);
+                    window.set_size(vw, vh + m + ph + m + gh + m + sh + lh + th + th + m);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Show estimates of the exported size on the gauge, skipping to the latest snapshot sent
+fn spawn_estimator(
+    params: Arc<RwLock<Params>>,
+    mut gauge: Progress,
+    to_app: app::Sender<Event>,
+    priority: Priority,
+    calibration: f64,
+) -> mpsc::Sender<(Params, Preview, usize)> {
+    let (to_estimator, for_estimator) = mpsc::channel::<(Params, Preview, usize)>();
+    thread::spawn(move || {
+        while let Ok(mut job) = for_estimator.recv() {
+            // Skip to the latest request
+            while let Ok(newer) = for_estimator.try_recv() {
+                job = newer;
+            }
+            let (working, snapshot, original) = job;
+            let untargeted = || *params.read().expect("params") != working;
+            if untargeted() {
+                continue;
+            }
+            let estimate = match snapshot.measure(priority) {
+                Ok(estimate) => estimate,
+                Err(e) => {
+                    to_app.send(Event::Error(format!("{e:#}")));
+                    continue;
+                }
+            };
+            if untargeted() {
+                continue;
+            }
+            #[allow(clippy::cast_precision_loss)]
+            let (estimate, original) = (estimate as f64 * calibration, original as f64);
+            gauge.set_value(estimate);
+            gauge.set_label(&format_change(estimate, original));
+            gauge.redraw();
+            app::awake();
+        }
+    });
+    to_estimator
+}
+
+/// Find the source pixel under a point in the view
+fn locate(
+    view: &Frame,
+    (sw, sh): (usize, usize),
+    viewport: &Viewport,
+    (x, y): (i32, i32),
+) -> Option<(usize, usize)> {
+    #[allow(clippy::cast_sign_loss)]
+    let view_size = (view.width() as usize, view.height() as usize);
+    let point = (f64::from(x - view.x()), f64::from(y - view.y()));
+    let (ix, iy) = viewport.locate(viewport.extent(sw, sh), view_size, point)?;
+
+    let (x0, y0, w) = viewport.crop.map_or((0, 0, sw), |c| (c.x, c.y, c.width));
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let position = (x0 + ix as usize % w, y0 + iy as usize);
+    Some(position)
+}
+
+fn show_params(sliders: &mut [HorSlider; 5], inputs: &mut [IntInput; 5], params: &Params) {
+    let values: [f64; 5] = [
+        params.effort.into(),
+        params.preservation.into(),
+        params.colors.into(),
+        params.dithering.into(),
+        params.posterization.into(),
+    ];
+    for ((slider, input), value) in sliders.iter_mut().zip(inputs).zip(values) {
+        slider.set_value(value);
+        input.set_value(&value.to_string());
+    }
+}
+
+/// Channel shown alone by pressing its initial
+fn channel_for(key: Key) -> Option<Channel> {
+    match key.to_char()? {
+        'r' => Some(Channel::Red),
+        'g' => Some(Channel::Green),
+        'b' => Some(Channel::Blue),
+        'a' => Some(Channel::Alpha),
+        _ => None,
+    }
+}
+
+/// Ask for a source file to open
+pub fn choose_source() -> Option<PathBuf> {
+    let mut chooser = NativeFileChooser::new(NativeFileChooserType::BrowseFile);
+    chooser.set_title("Open PNG");
+    chooser.set_filter("PNG images\t*.png");
+    chooser.show();
+    let path = chooser.filename();
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+fn fill_recent(menu: &mut MenuButton, recent: &[PathBuf], to_worker: &mpsc::Sender<Action>) {
+    menu.clear();
+    for path in recent {
+        // Escape characters with special meaning in menu labels
+        let label: String = path
+            .display()
+            .to_string()
+            .chars()
+            .flat_map(|c| ["\\/&_".contains(c).then_some('\\'), Some(c)])
+            .flatten()
+            .collect();
+        let (to_worker, path) = (to_worker.clone(), path.clone());
+        menu.add(&label, Shortcut::None, MenuFlag::Normal, move |_| {
+            to_worker.send(Action::Open(path.clone())).expect("worker");
+        });
+    }
+}
+
+fn title(path: &Path) -> String {
+    format!(
+        "{} · pngquant-interactive",
+        if is_stdin(path) {
+            Cow::from("stdin")
+        } else {
+            path.file_name().expect("file").to_string_lossy()
+        }
+    )
+}
@@ -0,0 +1,224 @@
+use crate::args::Args;
+use anyhow::{bail, Result};
+use pngquant_interactive::atlas::Atlas;
+use pngquant_interactive::attributes::AdvancedAttributes;
+use pngquant_interactive::compare::{Comparison, Thresholds};
+use pngquant_interactive::encode::{Encode, Priority};
+use pngquant_interactive::export::{is_same_file, original_size, Exporter};
+use pngquant_interactive::preview::{Params, Preview};
+use pngquant_interactive::source::Source;
+use pngquant_interactive::{info, palette};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// Check the quantized source against a baseline, failing beyond the thresholds
+pub fn compare(
+    args: &Args,
+    exporter: &Exporter,
+    source: Source,
+    attributes: &AdvancedAttributes,
+    params: &Params,
+    baseline_path: &Path,
+) -> Result<()> {
+    let mut preview = args.preview(source, attributes);
+    preview.quantize(params)?;
+    let mut data = Vec::new();
+    preview.encode(Priority::Size, &mut data)?;
+    let data = exporter.optimize(&data)?;
+    let candidate = Source::try_from(&*data)?;
+
+    let baseline = args.load(baseline_path)?;
+    let baseline_size = usize::try_from(fs::metadata(baseline_path)?.len())?;
+    Comparison::new(&candidate, data.len(), &baseline, baseline_size)?.check(&Thresholds {
+        size_delta: args.max_size_delta,
+        pixel_diff: args.max_pixel_diff,
+    })
+}
+
+/// Export the sources with one shared palette
+pub fn atlas(
+    args: &Args,
+    exporter: &Exporter,
+    source: Source,
+    attributes: &AdvancedAttributes,
+    params: &Params,
+) -> Result<()> {
+    let sources = iter::once(Ok(source))
+        .chain(args.atlas.iter().map(|p| args.load(p)))
+        .collect::<Result<Vec<_>>>()?;
+    let Atlas {
+        sheet,
+        importance,
+        tiles,
+    } = Atlas::try_from(sources)?;
+
+    let mut preview = args.preview(sheet, attributes);
+    preview.importance.replace(importance);
+    preview.quantize(params)?;
+    println!("shared palette: {} colors", preview.palette().len());
+    if let Some(ref preset) = args.save_preset {
+        params.save(preset)?;
+    }
+    if let Some(ref path) = args.export_palette {
+        palette::save(preview.palette(), path)?;
+    }
+
+    if let Some(ref path) = args.atlas_sheet {
+        preview.encode(Priority::Size, BufWriter::new(File::create(path)?))?;
+    }
+    for (path, tile) in iter::once(args.path())
+        .chain(args.atlas.iter().map(PathBuf::as_path))
+        .zip(tiles)
+    {
+        let output = exporter.path(path, params)?;
+        if !exporter.may_write(path, &output) {
+            info!("Skipping existing {} without --force", output.display());
+            continue;
+        }
+        let mut region = preview.region(0, tile.y, tile.width, tile.height);
+        region.source.chunks = tile.chunks;
+        let mut data = Vec::new();
+        region.encode(Priority::Size, &mut data)?;
+        let written = exporter.write(path, &output, &exporter.optimize(&data)?)?;
+        println!("{}: {written} bytes", output.display());
+    }
+    Ok(())
+}
+
+/// Export each source in turn, summarizing the results
+pub fn batch(
+    args: &Args,
+    exporter: &Exporter,
+    source: Source,
+    attributes: &AdvancedAttributes,
+    params: &Params,
+) -> Result<()> {
+    let paths: Vec<_> = iter::once(args.path())
+        .chain(args.more_paths.iter().map(PathBuf::as_path))
+        .collect();
+    let sources = iter::once(Ok(source)).chain(args.more_paths.iter().map(|p| args.load(p)));
+    let replaced = paths
+        .iter()
+        .filter(|p| exporter.path(p, params).is_ok_and(|o| is_same_file(&o, p)))
+        .count();
+    if replaced > 0 {
+        info!("Replacing {replaced} of {} sources in place", paths.len());
+    }
+    if let Some(region) = args.palette_region {
+        info!("Palettes derived from the {region} region of each source");
+    }
+    if let Some(ref preset) = args.save_preset {
+        params.save(preset)?;
+    }
+    let mut summary = Vec::new();
+    for (path, source) in paths.iter().zip(sources) {
+        let result = source.and_then(|source| {
+            let output = exporter.path(path, params)?;
+            if !exporter.may_write(path, &output) {
+                info!("Skipping existing {} without --force", output.display());
+                return Ok(None);
+            }
+            let original = original_size(path, &source)?;
+            let mut preview = args.preview(source, attributes);
+            let mut data = Vec::new();
+            if args.lossless {
+                preview.source.encode(Priority::Size, &mut data)?;
+            } else {
+                let params = match args.max_bytes {
+                    Some(max_bytes) => {
+                        let (fitted, size) = preview.fit(params, max_bytes)?;
+                        if size > max_bytes {
+                            eprintln!(
+                                "Warning: {}: smallest output is estimated at {size} bytes, over {max_bytes}",
+                                path.display()
+                            );
+                        }
+                        fitted
+                    }
+                    None => params.clone(),
+                };
+                preview.quantize(&params)?;
+                preview.encode(Priority::Size, &mut data)?;
+            }
+            let written = exporter.write(path, &output, &exporter.optimize(&data)?)?;
+            Ok(Some((original, written)))
+        });
+        if let Err(ref e) = result {
+            eprintln!("{}: {e}", path.display());
+        }
+        summary.push((path, result));
+    }
+
+    for (path, result) in &summary {
+        if let Ok(Some((original, exported))) = result {
+            println!("{}: {original} → {exported} bytes", path.display());
+        }
+    }
+    let failures = summary.iter().filter(|(_, r)| r.is_err()).count();
+    if failures > 0 {
+        bail!("{failures} of {} files failed", summary.len());
+    }
+    Ok(())
+}
+
+/// Write the quantized preview to standard output
+pub fn stdout(args: &Args, mut preview: Preview, params: &Params) -> Result<()> {
+    preview.quantize(params)?;
+    if let Some(region) = preview.palette_region() {
+        info!("Palette derived from the {region} region");
+    }
+    if let Some(ref preset) = args.save_preset {
+        params.save(preset)?;
+    }
+    if let Some(ref path) = args.export_palette {
+        palette::save(preview.palette(), path)?;
+    }
+    preview.encode(Priority::Size, BufWriter::new(io::stdout().lock()))
+}
+
+/// Export the preview, describing the result as JSON
+pub fn json(args: &Args, exporter: &Exporter, mut preview: Preview, params: &Params) -> Result<()> {
+    let output = exporter.path(args.path(), params)?;
+    if !exporter.may_write(args.path(), &output) {
+        bail!("{} already exists; use --force", output.display());
+    }
+    let original = original_size(args.path(), &preview.source)?;
+    let mut data = Vec::new();
+    if args.lossless {
+        preview.source.encode(Priority::Size, &mut data)?;
+    } else {
+        preview.quantize(params)?;
+        preview.encode(Priority::Size, &mut data)?;
+        if let Some(ref path) = args.export_palette {
+            palette::save(preview.palette(), path)?;
+        }
+        if let Some(ref preset) = args.save_preset {
+            params.save(preset)?;
+        }
+    }
+    let data = exporter.optimize(&data)?;
+    let written = exporter.write(args.path(), &output, &data)?;
+
+    let result = serde_json::json!({
+        "input": args.path().to_string_lossy(),
+        "output": output.to_string_lossy(),
+        "width": preview.source.width,
+        "height": preview.source.height,
+        "colors": (!args.lossless).then(|| preview.palette().len()),
+        "original_bytes": original,
+        "output_bytes": written,
+        "kept_original": written != data.len(),
+        "palette_region": preview.palette_region().map(|r| serde_json::json!({
+            "x": r.x,
+            "y": r.y,
+            "width": r.width,
+            "height": r.height,
+        })),
+        "quality": preview.quality,
+        "params": (!args.lossless).then_some(params),
+    });
+    println!("{result}");
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::mem;
+
+/// Committed values that can be stepped back and forth through
+pub struct History<T> {
+    past: Vec<T>,
+    present: T,
+    future: Vec<T>,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    pub const fn new(present: T) -> Self {
+        Self {
+            past: Vec::new(),
+            present,
+            future: Vec::new(),
+        }
+    }
+
+    /// Record a changed value, discarding anything undone
+    pub fn commit(&mut self, value: T) {
+        if value != self.present {
+            self.past.push(mem::replace(&mut self.present, value));
+            self.future.clear();
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<&T> {
+        let previous = self.past.pop()?;
+        self.future.push(mem::replace(&mut self.present, previous));
+        Some(&self.present)
+    }
+
+    pub fn redo(&mut self) -> Option<&T> {
+        let next = self.future.pop()?;
+        self.past.push(mem::replace(&mut self.present, next));
+        Some(&self.present)
+    }
+}
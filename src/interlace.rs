@@ -0,0 +1,173 @@
+//! Adam7 interlacing, which the png crate can decode but not encode
+
+use crate::encode::Priority;
+use crate::filter::Filter;
+use anyhow::{bail, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use png::chunk::{self, ChunkType};
+use std::io::Write;
+
+/// Origin and spacing of the pixels in each pass
+const PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Reorder samples into Adam7 passes, packing each row of a pass with the given function and then
+/// filtering it against the row above it in the pass
+pub fn passes(
+    samples: &[u8],
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    pack: impl Fn(&[u8]) -> Vec<u8>,
+    filter: Filter,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (x0, y0, dx, dy) in PASSES {
+        if x0 >= width {
+            continue;
+        }
+        let mut above: Option<Vec<u8>> = None;
+        for y in (y0..height).step_by(dy) {
+            let row = &samples[y * width * bytes_per_pixel..(y + 1) * width * bytes_per_pixel];
+            let pixels: Vec<u8> = (x0..width)
+                .step_by(dx)
+                .flat_map(|x| &row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel])
+                .copied()
+                .collect();
+            let packed = pack(&pixels);
+            data.extend(filter.filter_row(&packed, above.as_deref(), bytes_per_pixel));
+            above.replace(packed);
+        }
+    }
+    data
+}
+
+/// Rewrite an encoded PNG to declare interlacing and carry the given passes as its image data
+pub fn interlace(encoded: &[u8], passes: &[u8], priority: Priority) -> Result<Vec<u8>> {
+    let mut compressor = ZlibEncoder::new(
+        Vec::new(),
+        match priority {
+            Priority::Size => Compression::best(),
+            Priority::Speed => Compression::fast(),
+        },
+    );
+    compressor.write_all(passes)?;
+    let compressed = compressor.finish()?;
+
+    let (signature, mut rest) = encoded.split_at(8);
+    let mut output = signature.to_vec();
+    let mut written = false;
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            bail!("truncated chunk");
+        }
+        let length = usize::try_from(u32::from_be_bytes(rest[0..4].try_into()?))?;
+        let kind = ChunkType(rest[4..8].try_into()?);
+        let Some(body) = rest.get(8..8 + length) else {
+            bail!("truncated chunk");
+        };
+        match kind {
+            chunk::IHDR => {
+                let mut header = body.to_vec();
+                header[12] = 1; // Adam7
+                write_chunk(&mut output, kind, &header)?;
+            }
+            chunk::IDAT if !written => {
+                write_chunk(&mut output, kind, &compressed)?;
+                written = true;
+            }
+            chunk::IDAT => {}
+            _ => output.extend_from_slice(&rest[..12 + length]),
+        }
+        rest = &rest[12 + length..];
+    }
+    Ok(output)
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: ChunkType, data: &[u8]) -> Result<()> {
+    output.extend(u32::try_from(data.len())?.to_be_bytes());
+    let start = output.len();
+    output.extend(kind.0);
+    output.extend_from_slice(data);
+    let crc = crc32fast::hash(&output[start..]);
+    output.extend(crc.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::Encode;
+    use crate::preview::{Params, Preview};
+    use crate::source::Source;
+
+    #[test]
+    fn orders_pixels_by_pass() {
+        // 3×2: passes 1, 4 and 6 each hold one pixel of the first row, and pass 7 the second row
+        let data = passes(&[1, 2, 3, 4, 5, 6], 3, 2, 1, <[u8]>::to_vec, Filter::None);
+        assert_eq!(data, [0, 1, 0, 3, 0, 2, 0, 4, 5, 6]);
+    }
+
+    #[test]
+    fn filters_against_the_row_above_in_the_pass() {
+        // 1×4: passes 1 and 5 each hold one row, with nothing above it, and pass 7 holds two
+        let data = passes(&[10, 20, 30, 40], 1, 4, 1, <[u8]>::to_vec, Filter::Up);
+        assert_eq!(data, [2, 10, 2, 30, 2, 20, 2, 20]);
+    }
+
+    fn decoded(data: &[u8]) -> Vec<u8> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn decodes_as_the_progressive_image() {
+        let (width, height) = (13, 11);
+        let mut source = Vec::new();
+        let mut encoder = png::Encoder::new(&mut source, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        let pixels: Vec<u8> = (0..width * height * 3)
+            .map(|n| u8::try_from(n * 7 % 256).unwrap())
+            .collect();
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&pixels)
+            .unwrap();
+
+        for colors in [2, 4, 16, 256] {
+            for filter in [Filter::None, Filter::Paeth, Filter::Adaptive] {
+                let params = Params {
+                    colors,
+                    dithering: 5,
+                    effort: 5,
+                    preservation: 100,
+                    posterization: 0,
+                };
+                let encode = |interlace| {
+                    let mut source = Source::try_from(source.as_slice()).unwrap();
+                    source.filter = Some(filter);
+                    let mut preview = Preview::from(source);
+                    preview.interlace = interlace;
+                    preview.quantize(&params).unwrap();
+                    let mut data = Vec::new();
+                    preview.encode(Priority::Size, &mut data).unwrap();
+                    data
+                };
+                assert_eq!(decoded(&encode(true)), decoded(&encode(false)));
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Quantization and encoding behind pngquant-interactive, usable without its GUI
+
+#![warn(clippy::nursery, clippy::pedantic)]
+#![allow(
+    clippy::derive_partial_eq_without_eq,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    clippy::similar_names,
+    clippy::struct_excessive_bools,
+    clippy::too_many_lines
+)]
+
+pub mod atlas;
+pub mod attributes;
+pub mod chunks;
+pub mod color_key;
+pub mod compare;
+pub mod config;
+pub mod crop;
+pub mod encode;
+pub mod export;
+pub mod filter;
+pub mod history;
+pub mod interlace;
+pub mod log;
+pub mod metrics;
+pub mod palette;
+pub mod preset;
+pub mod preview;
+pub mod source;
+pub mod status;
+pub mod template;
+pub mod transform;
+pub mod utilities;
+
+pub use encode::{Encode, Priority};
+pub use preview::{Params, Preview};
+pub use source::Source;
+
+use crate::chunks::Chunk;
+use anyhow::Result;
+
+/// Quantize an encoded PNG with the given parameters, returning the encoded result with the
+/// metadata the CLI keeps by default
+pub fn quantize_png(data: &[u8], params: &Params) -> Result<Vec<u8>> {
+    let mut source = Source::try_from(data)?;
+    source.chunks.retain(Chunk::is_preserved);
+    let mut preview = Preview::from(source);
+    preview.quantize(params)?;
+    let mut output = Vec::new();
+    preview.encode(Priority::Size, &mut output)?;
+    Ok(output)
+}
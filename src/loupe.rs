@@ -0,0 +1,37 @@
+use rgb::RGBA8;
+
+/// Pixels shown on each side of the inspected pixel
+const RADIUS: usize = 7;
+
+/// Magnification of each pixel
+const SCALE: usize = 8;
+
+/// Width and height of the magnified image
+pub const SIZE: usize = (RADIUS * 2 + 1) * SCALE;
+
+/// Magnify the neighborhood of a pixel, with a grid between pixels and a frame around the center
+pub fn magnify(pixels: &[RGBA8], width: usize, (x, y): (usize, usize)) -> Vec<RGBA8> {
+    let height = pixels.len() / width;
+    let grid = RGBA8::new(0, 0, 0, u8::MAX);
+    let crosshair = RGBA8::new(u8::MAX, u8::MAX, u8::MAX, u8::MAX);
+
+    let mut magnified = Vec::with_capacity(SIZE * SIZE);
+    for my in 0..SIZE {
+        for mx in 0..SIZE {
+            let (cx, cy) = (mx / SCALE, my / SCALE);
+            let edge = mx % SCALE == 0 || my % SCALE == 0;
+            let outer = mx % SCALE == SCALE - 1 || my % SCALE == SCALE - 1;
+            magnified.push(if cx == RADIUS && cy == RADIUS && (edge || outer) {
+                crosshair
+            } else if edge {
+                grid
+            } else {
+                match ((x + cx).checked_sub(RADIUS), (y + cy).checked_sub(RADIUS)) {
+                    (Some(px), Some(py)) if px < width && py < height => pixels[py * width + px],
+                    _ => RGBA8::default(),
+                }
+            });
+        }
+    }
+    magnified
+}
@@ -0,0 +1,20 @@
+use rgb::RGBA8;
+
+/// Mean squared error across all channels
+pub fn mse(original: &[RGBA8], quantized: &[RGBA8]) -> f64 {
+    let squared_error: u64 = original
+        .iter()
+        .zip(quantized)
+        .flat_map(|(o, q)| [(o.r, q.r), (o.g, q.g), (o.b, q.b), (o.a, q.a)])
+        .map(|(a, b)| u64::from(a.abs_diff(b)).pow(2))
+        .sum();
+
+    #[allow(clippy::cast_precision_loss)]
+    let mse = squared_error as f64 / (original.len() * 4).max(1) as f64;
+    mse
+}
+
+/// Peak signal-to-noise ratio in decibels across all channels, infinite when identical
+pub fn psnr(original: &[RGBA8], quantized: &[RGBA8]) -> f64 {
+    10.0 * (f64::from(u8::MAX).powi(2) / mse(original, quantized)).log10()
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use rgb::RGBA8;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Number of pixels mapped to each palette entry
+pub fn usage(indices: &[u8], colors: usize) -> Vec<usize> {
+    let mut counts = vec![0; colors];
+    for &i in indices {
+        counts[usize::from(i)] += 1;
+    }
+    counts
+}
+
+/// Draw a bar for each palette entry in its own color, scaled to the most used entry
+pub fn histogram(palette: &[RGBA8], counts: &[usize], width: usize, height: usize) -> Vec<RGBA8> {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let bars: Vec<_> = (0..width)
+        .map(|x| {
+            let entry = x * palette.len() / width;
+            let bar = (counts[entry] * height).div_ceil(max);
+            (palette[entry].rgb().with_alpha(u8::MAX), bar)
+        })
+        .collect();
+
+    let mut pixels = vec![RGBA8::default(); width * height];
+    for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+        for (pixel, &(color, bar)) in row.iter_mut().zip(&bars) {
+            if height - y <= bar {
+                *pixel = color;
+            }
+        }
+    }
+    pixels
+}
+
+/// Swatches per row of the palette panel, which has room for the largest palette
+const COLUMNS: usize = 32;
+const ROWS: usize = 8;
+
+fn swatch_size(width: usize, height: usize) -> usize {
+    (width / COLUMNS).min(height / ROWS).max(1)
+}
+
+/// Index of the swatch under a point in a panel of the given size
+pub fn swatch_at((x, y): (usize, usize), width: usize, height: usize) -> Option<usize> {
+    let size = swatch_size(width, height);
+    let (column, row) = (x / size, y / size);
+    (column < COLUMNS && row < ROWS).then_some(row * COLUMNS + column)
+}
+
+/// Draw the palette as a grid of swatches from the top left of a panel of the given size, with a
+/// corner marking those whose colors are locked
+pub fn swatches(
+    palette: &[RGBA8],
+    locked: &[RGBA8],
+    width: usize,
+    height: usize,
+) -> (Vec<RGBA8>, usize, usize) {
+    let size = swatch_size(width, height);
+    let (width, height) = (COLUMNS * size, ROWS * size);
+    let gap = usize::from(size >= 4);
+
+    let mut pixels = vec![RGBA8::default(); width * height];
+    for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let (sx, sy) = (x % size, y % size);
+            let Some(&color) = palette
+                .get((y / size) * COLUMNS + x / size)
+                .filter(|_| sx >= gap && sy >= gap)
+            else {
+                continue;
+            };
+            *pixel = if locked.contains(&color) && sx + sy < gap * 2 + size / 3 {
+                contrasting(color)
+            } else {
+                color
+            };
+        }
+    }
+    (pixels, width, height)
+}
+
+/// Black or white, whichever stands out against the given color
+fn contrasting(color: RGBA8) -> RGBA8 {
+    let luma = u32::from(color.r) * 299 + u32::from(color.g) * 587 + u32::from(color.b) * 114;
+    let level = if luma < 128_000 { u8::MAX } else { 0 };
+    RGBA8::new(level, level, level, u8::MAX)
+}
+
+/// Write the palette as a GIMP palette if the path ends in .gpl, or else as an Adobe color table
+pub fn save(palette: &[RGBA8], path: &Path) -> Result<()> {
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+    if extension.eq_ignore_ascii_case("gpl") {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut gpl = format!("GIMP Palette\nName: {name}\nColumns: 16\n#\n");
+        for (index, p) in palette.iter().enumerate() {
+            writeln!(gpl, "{:3} {:3} {:3}\tIndex {index}", p.r, p.g, p.b)?;
+        }
+        fs::write(path, gpl)?;
+    } else {
+        // 256 RGB entries, then the count of those used and the index of any transparent one
+        let mut act = vec![0; 256 * 3];
+        for (entry, p) in act.chunks_exact_mut(3).zip(palette) {
+            entry.copy_from_slice(&[p.r, p.g, p.b]);
+        }
+        let transparent = palette.iter().position(|p| p.a == 0).unwrap_or(0xFFFF);
+        act.extend(u16::try_from(palette.len())?.to_be_bytes());
+        act.extend(u16::try_from(transparent)?.to_be_bytes());
+        fs::write(path, act)?;
+    }
+    Ok(())
+}
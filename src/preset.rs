@@ -0,0 +1,29 @@
+use crate::preview::Params;
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+impl Params {
+    pub fn load(path: &Path) -> Result<Self> {
+        let params: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        macro_rules! validate {
+            ($field:ident, $range:expr) => {
+                if !$range.contains(&params.$field) {
+                    bail!("{} must be within {:?}", stringify!($field), $range);
+                }
+            };
+        }
+        validate!(colors, 2..=256);
+        validate!(dithering, 0..=10);
+        validate!(effort, 1..=10);
+        validate!(preservation, 0..=100);
+        validate!(posterization, 0..=4);
+
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, serde_json::to_string_pretty(self)? + "\n")?)
+    }
+}
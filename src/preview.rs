@@ -1,113 +1,661 @@
+use crate::attributes::AdvancedAttributes;
+use crate::chunks::{write_chunks, Chunk, Position};
+use crate::color_key::ColorKey;
+use crate::crop::Crop;
+use crate::debug;
 use crate::encode::{Encode, Priority};
+use crate::filter::Filter;
+use crate::interlace;
+use crate::metrics;
+use crate::palette;
 use crate::source::Source;
-use crate::utilities::{CachedOption, RGBAs};
-use anyhow::Result;
-use fltk::enums::ColorDepth::Rgba8;
-use fltk::image::RgbImage;
-use fltk::prelude::ImageExt;
-use imagequant::{Attributes, QuantizationResult};
-use png::{ColorType, Encoder};
-use rgb::{ComponentBytes, RGBA8};
+use crate::transform::{recenter, transform, weigh_importance};
+use crate::utilities::{crop, index_depth, pack, subsample, CachedOption, RGBAs};
+use anyhow::{bail, Result};
+use imagequant::{Attributes, ControlFlow, Image, QuantizationResult};
+use png::{chunk, BitDepth, ColorType, Encoder};
+use rgb::{ComponentBytes, RGB8, RGBA8};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
+use std::time::{Duration, Instant};
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Params {
+    pub colors: u16,
     pub dithering: u8,
     pub effort: u8,
     pub preservation: u8,
+    pub posterization: u8,
+}
+
+/// Pixels to find a palette from while drafting
+const DRAFT_PIXELS: usize = 512 * 512;
+
+/// Importance of pixels not painted, so that painting can lower it as well as raise it
+pub const UNPAINTED_IMPORTANCE: u8 = u8::MAX / 4;
+
+/// Result of remapping the source to a quantized palette at one dithering level and background
+struct Remapping {
+    palette_rgba: Vec<RGBA8>,
+    quantized_indexed: Vec<u8>,
+    quality: Option<u8>,
 }
 
 pub struct Preview {
     pub source: Source,
+    pub attributes: AdvancedAttributes,
+    pub color_key: Option<ColorKey>,
+    pub palette_size: Option<usize>,
+    pub elapsed: Option<Duration>,
+    pub quality: Option<u8>,
+    pub importance: Option<Vec<u8>>,
+    pub weights: Option<[f32; 4]>,
+    pub linear: bool,
+    pub draft: bool,
+    pub fixed_colors: Vec<RGBA8>,
+    pub locked: Vec<RGBA8>,
+    pub last_index_transparent: bool,
+    pub background: Option<RGB8>,
+    pub output_gamma: Option<f64>,
+    pub interlace: bool,
+    pub grayscale: bool,
+    /// Rectangle of the source that the palette is found from, rather than all of it
+    palette_region: Option<Crop>,
     quantizer: Attributes,
-    quantization: CachedOption<(u8, u8), QuantizationResult>,
+    /// Pixels as transformed for quantization, from which each image given to imagequant is made
+    /// afresh since quantizing alters it
+    prepared: Option<Prepared>,
+    sample: CachedOption<Option<usize>, Prepared>,
+    quantization: CachedOption<(u8, u8, u16, u8, bool), QuantizationResult>,
+    remappings: HashMap<(u8, Option<RGB8>), Remapping>,
+    remapped: Option<(u8, Option<RGB8>)>,
+    edits: BTreeMap<usize, RGBA8>,
     palette_rgba: Option<Vec<RGBA8>>,
     quantized_indexed: Option<Vec<u8>>,
-    quantized_rgba: Option<Vec<u8>>,
+    quantized_rgba: Option<Vec<RGBA8>>,
+    /// Count of changes to the quantized pixels
+    revision: u64,
+}
+
+/// Pixels transformed for quantization, with the importance of each
+struct Prepared {
+    pixels: Vec<RGBA8>,
+    size: (usize, usize),
+    importance: Option<Vec<u8>>,
+    matte: Option<(RGB8, Vec<RGBA8>)>,
+}
+
+impl Prepared {
+    fn new(
+        pixels: &[RGBA8],
+        size: (usize, usize),
+        importance: Option<Vec<u8>>,
+        linear: bool,
+        weights: Option<[f32; 4]>,
+    ) -> Self {
+        let importance = match weights {
+            Some(weights) => weigh_importance(pixels, importance.as_deref(), weights),
+            None => importance,
+        };
+        Self {
+            pixels: transform(pixels, linear, weights),
+            size,
+            importance,
+            matte: None,
+        }
+    }
+
+    /// Fill an opaque background behind the pixels, reusing the fill while its color is unchanged
+    fn set_background(&mut self, background: Option<RGB8>) {
+        if self.matte.as_ref().map(|&(color, _)| color) != background {
+            let (width, height) = self.size;
+            self.matte = background.map(|b| (b, vec![b.with_alpha(u8::MAX); width * height]));
+        }
+    }
 }
 
 impl Preview {
-    pub fn display(&mut self, width: usize, height: usize) -> Result<RgbImage> {
-        let quantized_rgba = self.quantized_rgba.get_or_insert_with(|| {
+    /// Find the highest preservation estimated to fit within the given size, or else the lowest
+    pub fn fit(&mut self, params: &Params, max_bytes: usize) -> Result<(Params, usize)> {
+        let mut fitted = Params {
+            preservation: 0,
+            ..params.clone()
+        };
+        self.quantize(&fitted)?;
+        let mut size = self.estimate()?;
+        if size > max_bytes {
+            return Ok((fitted, size));
+        }
+
+        let mut max = 100;
+        while fitted.preservation < max {
+            let candidate = Params {
+                preservation: (fitted.preservation + max).div_ceil(2),
+                ..params.clone()
+            };
+            self.quantize(&candidate)?;
+            let estimate = self.estimate()?;
+            if estimate <= max_bytes {
+                (fitted, size) = (candidate, estimate);
+            } else {
+                max = candidate.preservation - 1;
+            }
+        }
+        Ok((fitted, size))
+    }
+
+    pub fn clear_importance(&mut self) {
+        if self.importance.take().is_some() {
+            self.invalidate();
+        }
+    }
+
+    /// Set how important a disc of the source is to preserve
+    pub fn paint(&mut self, x: usize, y: usize, radius: usize, strength: u8) {
+        let (width, height) = (self.source.width, self.source.height);
+        let importance = self
+            .importance
+            .get_or_insert_with(|| vec![UNPAINTED_IMPORTANCE; width * height]);
+        for py in y.saturating_sub(radius)..(y + radius + 1).min(height) {
+            for px in x.saturating_sub(radius)..(x + radius + 1).min(width) {
+                if px.abs_diff(x).pow(2) + py.abs_diff(y).pow(2) <= radius.pow(2) {
+                    importance[py * width + px] = strength;
+                }
+            }
+        }
+        self.invalidate();
+    }
+
+    /// Number of pixels mapped to each palette entry
+    pub fn usage(&self) -> Vec<usize> {
+        let indices = self.quantized_indexed.as_ref().expect("quantized");
+        palette::usage(indices, self.palette().len())
+    }
+
+    /// Palette of the latest quantization, if any
+    pub fn palette(&self) -> &[RGBA8] {
+        self.palette_rgba.as_deref().unwrap_or_default()
+    }
+
+    /// Lock or unlock the color of a palette entry, which is kept when quantizing again
+    pub fn toggle_lock(&mut self, index: usize) {
+        let Some(&color) = self.palette().get(index) else {
+            return;
+        };
+        if let Some(position) = self.locked.iter().position(|&c| c == color) {
+            self.locked.remove(position);
+        } else {
+            self.locked.push(color);
+        }
+        self.invalidate();
+    }
+
+    /// Replace a palette entry while keeping the indices of the latest quantization
+    pub fn recolor(&mut self, index: usize, color: RGBA8) {
+        let Some(entry) = self.palette_rgba.as_mut().and_then(|p| p.get_mut(index)) else {
+            return;
+        };
+        *entry = color;
+        self.edits.insert(index, color);
+        self.quantized_rgba.take();
+        self.revision += 1;
+    }
+
+    /// Whether entries of the latest quantization's palette were replaced
+    pub fn is_edited(&self) -> bool {
+        !self.edits.is_empty()
+    }
+
+    /// Count of changes to the quantized pixels, by which to tell whether something made from them
+    /// is current
+    pub const fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Palette index of a pixel in the latest quantization, if any
+    pub fn index_at(&self, x: usize, y: usize) -> Option<u8> {
+        let indices = self.quantized_indexed.as_ref()?;
+        Some(indices[y * self.source.width + x])
+    }
+
+    /// Pixels of the source alongside those of the latest quantization
+    pub fn both_pixels(&mut self) -> (&[RGBA8], &[RGBA8]) {
+        self.pixels();
+        let quantized = self.quantized_rgba.as_deref().expect("quantized");
+        (&self.source.rgba, quantized)
+    }
+
+    pub fn pixels(&mut self) -> &[RGBA8] {
+        self.quantized_rgba.get_or_insert_with(|| {
             let palette = self.palette_rgba.as_ref().expect("quantized");
             let indices = self.quantized_indexed.as_ref().expect("quantized");
-            indices
-                .iter()
-                .flat_map(|&i| palette[usize::from(i)].iter())
-                .collect()
+            indices.iter().map(|&i| palette[usize::from(i)]).collect()
+        })
+    }
+
+    /// Mean squared error per channel of the latest remapping, as measured on its pixels
+    pub fn error(&mut self) -> f64 {
+        self.pixels();
+        let quantized = self.quantized_rgba.as_deref().expect("quantized");
+        metrics::mse(&self.source.rgba, quantized)
+    }
+
+    pub fn psnr(&mut self) -> f64 {
+        self.pixels();
+        let quantized = self.quantized_rgba.as_deref().expect("quantized");
+        metrics::psnr(&self.source.rgba, quantized)
+    }
+
+    /// Report the percentage of quantization completed, aborting it unless the callback returns true
+    pub fn on_progress<F: Fn(f32) -> bool + Send + Sync + 'static>(&mut self, callback: F) {
+        self.quantizer.set_progress_callback(move |percent| {
+            if callback(percent) {
+                ControlFlow::Continue
+            } else {
+                ControlFlow::Break
+            }
         });
+    }
 
-        let mut image = RgbImage::new(
-            quantized_rgba,
-            self.source.width.try_into()?,
-            self.source.height.try_into()?,
-            Rgba8,
-        )?;
+    /// Sampling interval of a large source while drafting
+    fn draft_step(&self, pixels: usize) -> Option<usize> {
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_precision_loss,
+            clippy::cast_sign_loss
+        )]
+        let step = (pixels as f64 / DRAFT_PIXELS as f64).sqrt().ceil() as usize;
+        (self.draft && step > 1).then_some(step)
+    }
 
-        if width < self.source.width || height < self.source.height {
-            image.scale(width.try_into()?, height.try_into()?, true, false);
-        };
+    /// Discard the cached image and quantization after a change to what either is made from
+    fn invalidate(&mut self) {
+        self.prepared.take();
+        self.sample = CachedOption::default();
+        self.quantization = CachedOption::default();
+    }
 
-        Ok(image)
+    /// Rectangle of the source that the palette is found from, if not all of it
+    pub const fn palette_region(&self) -> Option<Crop> {
+        self.palette_region
+    }
+
+    /// Find the palette from only a rectangle of the source, still remapping all of it
+    pub fn set_palette_region(&mut self, region: Option<Crop>) {
+        if self.palette_region != region {
+            self.palette_region = region;
+            self.invalidate();
+        }
     }
 
     pub fn quantize(&mut self, params: &Params) -> Result<()> {
-        let mut image = self.quantizer.new_image_borrowed(
-            &self.source.rgba,
-            self.source.width,
-            self.source.height,
-            0.0,
-        )?;
-
-        let (e, p) = (params.effort, params.preservation);
-        let quantization = self.quantization.get_or_insert_with((e, p), || {
-            self.quantizer.set_speed(11 - i32::from(e)).unwrap();
-            self.quantizer.set_quality(0, p).unwrap();
-            self.quantizer.quantize(&mut image).unwrap()
+        let transforming = self.linear || self.weights.is_some();
+        let gamma = self.source.gamma();
+        // Linearized pixels are already in linear light, which imagequant mustn't convert again
+        let input_gamma = if self.linear {
+            1.0
+        } else {
+            gamma.unwrap_or(0.0)
+        };
+        let background = (self.background)
+            .map(|b| transform(&[b.with_alpha(u8::MAX)], self.linear, self.weights)[0].rgb());
+        let (width, height) = (self.source.width, self.source.height);
+        let region = self.palette_region.unwrap_or(Crop {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        if !region.fits(width, height) {
+            bail!("palette region {region} lies outside the {width}×{height} source");
+        }
+        let step = self.draft_step(region.width * region.height);
+        let locked = transform(&self.locked, self.linear, self.weights);
+        let fixed_colors: Vec<_> = self.fixed_colors.iter().chain(&locked).copied().collect();
+        let prepared = self.prepared.get_or_insert_with(|| {
+            Prepared::new(
+                &self.source.rgba,
+                (width, height),
+                self.importance.clone(),
+                self.linear,
+                self.weights,
+            )
+        });
+        prepared.set_background(background);
+        let prepared = &*prepared;
+
+        // Find the palette from a region, or from a sample of large sources while drafting
+        let sample = if self.palette_region.is_some() || step.is_some() {
+            let sample = self.sample.get_or_insert_with(step, || {
+                let Crop {
+                    x,
+                    y,
+                    width: w,
+                    height: h,
+                } = region;
+                let step = step.unwrap_or(1);
+                Prepared::new(
+                    &subsample(&crop(&self.source.rgba, width, x, y, w, h), w, step),
+                    (w.div_ceil(step), h.div_ceil(step)),
+                    (self.importance.as_deref())
+                        .map(|i| subsample(&crop(i, width, x, y, w, h), w, step)),
+                    self.linear,
+                    self.weights,
+                )
+            });
+            sample.set_background(background);
+            Some(&*sample)
+        } else {
+            None
+        };
+
+        // Leave room within a fixed palette size, including for the entry of a color key
+        let room = self.palette_size.map(|size| {
+            let keyed = self.color_key.is_some() && self.source.uses_alpha;
+            u16::try_from(size - usize::from(keyed)).unwrap_or(u16::MAX)
         });
+        let (e, p, c, b) = (
+            params.effort,
+            params.preservation,
+            room.map_or(params.colors, |room| params.colors.min(room)),
+            params.posterization,
+        );
+        let mut fresh = false;
+        let quantization =
+            self.quantization
+                .get_or_try_insert_with((e, p, c, b, step.is_some()), || {
+                    let start = Instant::now();
+                    self.quantizer.set_speed(11 - i32::from(e))?;
+                    let min_quality = self.attributes.min_quality.unwrap_or(0).min(p);
+                    self.quantizer.set_quality(min_quality, p)?;
+                    self.quantizer.set_max_colors(u32::from(c))?;
+                    self.quantizer.set_min_posterization(b)?;
+                    self.quantizer.set_last_index_transparent(
+                        self.last_index_transparent && self.source.uses_alpha,
+                    );
+                    let mut image = new_image(
+                        &self.quantizer,
+                        sample.unwrap_or(prepared),
+                        input_gamma,
+                        &fixed_colors,
+                    )?;
+                    let quantization = self.quantizer.quantize(&mut image)?;
+                    self.elapsed.replace(start.elapsed());
+                    fresh = true;
+                    self.edits.clear();
+                    Ok::<_, imagequant::Error>(quantization)
+                })?;
 
-        quantization.set_dithering_level(f32::from(params.dithering) / 10.0)?;
-        let (palette_rgba, quantized_indexed) = quantization.remapped(&mut image)?;
+        // Keep the remapping, and any edits to its palette, while nothing affecting it changed
+        if fresh {
+            self.remappings.clear();
+        } else {
+            debug!("Reusing quantization at effort {e}, preservation {p}");
+        }
+        let key = (params.dithering, self.background);
+        if !fresh && self.remapped == Some(key) {
+            return Ok(());
+        }
+        let remapping = match self.remappings.entry(key) {
+            Entry::Occupied(entry) => {
+                debug!("Reusing remapping at dithering {}", params.dithering);
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                if let Some(gamma) = self.output_gamma.or(gamma) {
+                    quantization.set_output_gamma(gamma)?;
+                }
+                quantization.set_dithering_level(f32::from(params.dithering) / 10.0)?;
+                let (mut palette_rgba, quantized_indexed) = quantization.remapped(
+                    &mut new_image(&self.quantizer, prepared, input_gamma, &fixed_colors)?,
+                )?;
+                if transforming {
+                    // Restore locked colors from their transformed counterparts rather than
+                    // recentering
+                    let restored: Vec<_> = (palette_rgba.iter().enumerate())
+                        .filter_map(|(i, p)| {
+                            Some((i, self.locked[locked.iter().position(|l| l == p)?]))
+                        })
+                        .collect();
+                    recenter(&mut palette_rgba, &self.source.rgba, &quantized_indexed);
+                    for (index, color) in restored {
+                        palette_rgba[index] = color;
+                    }
+                }
+                entry.insert(Remapping {
+                    palette_rgba,
+                    quantized_indexed,
+                    quality: quantization.remapping_quality(),
+                })
+            }
+        };
+        self.quality = remapping.quality;
+        let mut palette_rgba = remapping.palette_rgba.clone();
+        let quantized_indexed = remapping.quantized_indexed.clone();
+        for (&index, &color) in &self.edits {
+            if let Some(entry) = palette_rgba.get_mut(index) {
+                *entry = color;
+            }
+        }
 
+        self.remapped.replace(key);
         self.quantized_rgba.take();
+        self.revision += 1;
         self.palette_rgba.replace(palette_rgba);
         self.quantized_indexed.replace(quantized_indexed);
         Ok(())
     }
 }
 
+impl Preview {
+    #[must_use]
+    pub fn region(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let indexed = self.quantized_indexed.as_ref().expect("quantized");
+        Self {
+            attributes: self.attributes.clone(),
+            color_key: self.color_key,
+            palette_size: self.palette_size,
+            output_gamma: self.output_gamma,
+            interlace: self.interlace,
+            grayscale: self.grayscale,
+            palette_rgba: self.palette_rgba.clone(),
+            quantized_indexed: Some(crop(indexed, self.source.width, x, y, width, height)),
+            ..Self::from(self.source.region(x, y, width, height))
+        }
+    }
+}
+
+impl Preview {
+    /// Copy of what's needed to encode the latest quantization
+    #[must_use]
+    pub fn snapshot(&self) -> Self {
+        self.region(0, 0, self.source.width, self.source.height)
+    }
+}
+
 impl Encode for Preview {
-    fn encode<W: Write>(&self, priority: Priority, into: W) -> Result<()> {
+    fn encode<W: Write>(&self, priority: Priority, mut into: W) -> Result<()> {
         let Source { width, height, .. } = self.source;
-        let mut encoder = Encoder::new(into, width.try_into()?, height.try_into()?);
+
+        // Interlaced image data is substituted into a complete encoding afterward
+        let mut plain = Vec::new();
+        let target: &mut dyn Write = if self.interlace {
+            &mut plain
+        } else {
+            &mut into
+        };
+        let mut encoder = Encoder::new(target, width.try_into()?, height.try_into()?);
         encoder.set_compression(priority.into());
-        encoder.set_color(ColorType::Indexed);
+        if let Some(filter) = self.source.filter {
+            filter.apply(&mut encoder);
+        }
 
         let palette_rgba = self.palette_rgba.as_ref().expect("quantized");
-        let palette_rgb = if self.source.uses_alpha {
+        let quantized_indexed = self.quantized_indexed.as_ref().expect("quantized");
+        let keyed = match self.color_key {
+            Some(key) if self.source.uses_alpha => {
+                Some(key.apply(palette_rgba, quantized_indexed)?)
+            }
+            _ => None,
+        };
+        let (palette_rgba, quantized_indexed) = keyed
+            .as_ref()
+            .map_or((palette_rgba, quantized_indexed), |(p, i)| (p, i));
+
+        // Sources with an alpha channel are often entirely opaque, needing no tRNS
+        let (mut palette_rgb, mut trns) = if keyed.is_some() {
+            (palette_rgba.without_alpha(), Some(vec![0]))
+        } else if self.source.uses_alpha && palette_rgba.iter().any(|p| p.a < u8::MAX) {
             let (rgb, a) = palette_rgba.separate_alpha();
-            encoder.set_trns(a);
-            rgb
+            (rgb, Some(a))
         } else {
-            palette_rgba.without_alpha()
+            (palette_rgba.without_alpha(), None)
         };
-        encoder.set_palette(palette_rgb.as_bytes());
+        if let Some(size) = self.palette_size {
+            if palette_rgb.len() > size {
+                bail!("{} colors exceed palette size {size}", palette_rgb.len());
+            }
+            palette_rgb.resize(size, RGB8::default());
+            if let Some(ref mut trns) = trns {
+                trns.resize(size, u8::MAX);
+            }
+        }
+
+        let depth = index_depth(palette_rgb.len());
+        let gray = match gray_depth(&palette_rgb) {
+            Some(gray_depth)
+                if self.grayscale
+                    && trns.iter().flatten().all(|&a| a == u8::MAX)
+                    && self.palette_size.is_none()
+                    && gray_depth <= depth =>
+            {
+                Some(gray_depth)
+            }
+            _ => None,
+        };
+        let levels = gray.map(|depth| {
+            let scale = u8::MAX / (u8::MAX >> (8 - depth));
+            let levels: Vec<u8> = palette_rgb.iter().map(|c| c.r / scale).collect();
+            quantized_indexed
+                .iter()
+                .map(|&i| levels[usize::from(i)])
+                .collect::<Vec<_>>()
+        });
+        let samples = levels.as_deref().unwrap_or(quantized_indexed);
+        let (color, depth) = gray.map_or((ColorType::Indexed, depth), |depth| {
+            (ColorType::Grayscale, depth)
+        });
+        encoder.set_color(color);
+        encoder.set_depth(BitDepth::from_u8(depth).expect("valid depth"));
+        let packed: Vec<u8> = samples
+            .chunks(width)
+            .flat_map(|row| pack(row, depth))
+            .collect();
+
+        // Palette is written manually so that preserved chunks can precede it
+        let mut chunks = self.source.chunks.clone();
+        if let Some(gamma) = self.output_gamma {
+            chunks.retain(|c| ![chunk::gAMA, chunk::iCCP, chunk::sRGB].contains(&c.kind));
+            chunks.push(Chunk::gamma(gamma));
+        }
+        let chunks = &chunks;
+        let mut writer = encoder.write_header()?;
+        write_chunks(&mut writer, chunks, Position::BeforePlte, gray.is_some())?;
+        if gray.is_none() {
+            writer.write_chunk(chunk::PLTE, palette_rgb.as_bytes())?;
+            if let Some(trns) = trns {
+                writer.write_chunk(chunk::tRNS, &trns)?;
+            }
+        }
+        write_chunks(&mut writer, chunks, Position::BeforeIdat, gray.is_some())?;
+        // Streaming skips the check for a palette given to the encoder
+        let mut stream = writer.stream_writer()?;
+        if self.interlace {
+            stream.write_all(&vec![0; packed.len()])?;
+        } else {
+            stream.write_all(&packed)?;
+        }
+        stream.finish()?;
+        write_chunks(&mut writer, chunks, Position::AfterIdat, gray.is_some())?;
+        writer.finish()?;
+
+        if self.interlace {
+            let filter = self.source.filter.unwrap_or(Filter::Sub);
+            let passes =
+                interlace::passes(samples, width, height, 1, |row| pack(row, depth), filter);
+            into.write_all(&interlace::interlace(&plain, &passes, priority)?)?;
+        }
+        Ok(())
+    }
+}
 
-        Ok(encoder
-            .write_header()?
-            .write_image_data(self.quantized_indexed.as_ref().expect("quantized"))?)
+/// Fewest bits per sample that represent every color of an all-gray palette exactly
+fn gray_depth(palette: &[RGB8]) -> Option<u8> {
+    if !palette.iter().all(|c| c.r == c.g && c.g == c.b) {
+        return None;
+    }
+    [1, 2, 4, 8].into_iter().find(|&depth| {
+        let scale = u8::MAX / (u8::MAX >> (8 - depth));
+        palette.iter().all(|c| c.r % scale == 0)
+    })
+}
+
+/// Prepare pixels for quantization along with what guides it
+fn new_image<'p>(
+    quantizer: &Attributes,
+    prepared: &'p Prepared,
+    gamma: f64,
+    fixed_colors: &[RGBA8],
+) -> Result<Image<'p>, imagequant::Error> {
+    let (width, height) = prepared.size;
+    let mut image = quantizer.new_image_borrowed(&prepared.pixels, width, height, gamma)?;
+    if let Some(ref importance) = prepared.importance {
+        image.set_importance_map(importance.as_slice())?;
+    }
+    for &color in fixed_colors {
+        image.add_fixed_color(color)?;
+    }
+    if let Some((_, ref matte)) = prepared.matte {
+        image.set_background(quantizer.new_image_borrowed(matte, width, height, gamma)?)?;
     }
+    Ok(image)
 }
 
 impl From<Source> for Preview {
     fn from(source: Source) -> Self {
         Self {
             source,
+            attributes: AdvancedAttributes::default(),
+            color_key: None,
+            palette_size: None,
+            elapsed: None,
+            quality: None,
+            importance: None,
+            weights: None,
+            linear: false,
+            draft: false,
+            fixed_colors: Vec::new(),
+            locked: Vec::new(),
+            last_index_transparent: false,
+            background: None,
+            output_gamma: None,
+            interlace: false,
+            grayscale: false,
+            palette_region: None,
             quantizer: imagequant::new(),
+            prepared: None,
+            sample: CachedOption::default(),
             quantization: CachedOption::default(),
+            remappings: HashMap::new(),
+            remapped: None,
+            edits: BTreeMap::new(),
             palette_rgba: None,
             quantized_indexed: None,
             quantized_rgba: None,
+            revision: 0,
         }
     }
 }
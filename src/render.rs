@@ -0,0 +1,151 @@
+use crate::loupe::magnify;
+use crate::viewport::{checkerboard, heatmap, Backdrop, Mode, Viewport};
+use pngquant_interactive::preview::Preview;
+use pngquant_interactive::utilities::{blend_over, crop, CachedOption};
+use rgb::{RGB8, RGBA8};
+use std::iter;
+
+/// Width in the view of a checkerboard cell shown behind transparency
+const CHECKER_SIZE: f64 = 8.0;
+
+/// Pixels of the preview within the view, and the size at which to draw them
+#[derive(Clone)]
+pub struct Rendering {
+    pub pixels: Vec<RGBA8>,
+    pub width: usize,
+    pub height: usize,
+    pub scaled: (usize, usize),
+}
+
+/// Latest rendering of a preview, reused while neither the preview nor how it’s shown changed
+#[derive(Default)]
+pub struct Renderer {
+    displayed: CachedOption<(u64, usize, usize, Viewport, Option<RGB8>), Rendering>,
+}
+
+impl Renderer {
+    pub fn display(
+        &mut self,
+        preview: &mut Preview,
+        width: usize,
+        height: usize,
+        viewport: &Viewport,
+    ) -> Rendering {
+        let key = (
+            preview.revision(),
+            width,
+            height,
+            *viewport,
+            preview.background,
+        );
+        self.displayed
+            .get_or_insert_with(key, || render(preview, width, height, viewport))
+            .clone()
+    }
+}
+
+fn render(preview: &mut Preview, width: usize, height: usize, viewport: &Viewport) -> Rendering {
+    let mode = viewport.mode;
+    let (sw, sh) = (preview.source.width, preview.source.height);
+    let (w, h) = viewport.extent(sw, sh);
+    let (uses_alpha, background) = (preview.source.uses_alpha, preview.background);
+    let (original, quantized) = if matches!(mode, Mode::Original) {
+        (preview.source.rgba.as_slice(), &[][..])
+    } else {
+        preview.both_pixels()
+    };
+    let cropped: (Vec<RGBA8>, Vec<RGBA8>);
+    let (original, quantized, stride) = match viewport.crop {
+        Some(c) => {
+            cropped = (
+                crop(original, sw, c.x, c.y, c.width, c.height),
+                crop(quantized, sw, c.x, c.y, c.width, c.height),
+            );
+            (cropped.0.as_slice(), cropped.1.as_slice(), c.width)
+        }
+        None => (original, quantized, sw),
+    };
+    let composite: Vec<RGBA8>;
+    let pixels = match mode {
+        Mode::Quantized => quantized,
+        Mode::Original => original,
+        Mode::SideBySide => {
+            composite = original
+                .chunks(stride)
+                .zip(quantized.chunks(stride))
+                .flat_map(|(o, q)| o.iter().chain(q))
+                .copied()
+                .collect();
+            &composite
+        }
+        Mode::Difference => {
+            composite = heatmap(original, quantized);
+            &composite
+        }
+        Mode::Split => {
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_precision_loss,
+                clippy::cast_sign_loss
+            )]
+            let split = ((viewport.divider * w as f64) as usize).min(w - 1);
+            composite = original
+                .chunks(w)
+                .zip(quantized.chunks(w))
+                .flat_map(|(o, q)| {
+                    let divider = RGBA8::new(u8::MAX, u8::MAX, u8::MAX, u8::MAX);
+                    (o[..split].iter().copied())
+                        .chain(iter::once(divider))
+                        .chain(q[split + 1..].iter().copied())
+                })
+                .collect();
+            &composite
+        }
+    };
+    let region = viewport.region((w, h), (width, height));
+    let mut visible = crop(pixels, w, region.x, region.y, region.width, region.height);
+    match (viewport.channel, background, viewport.backdrop) {
+        (Some(channel), ..) if !matches!(mode, Mode::Difference) => {
+            for pixel in &mut visible {
+                let value = channel.of(*pixel);
+                *pixel = RGBA8::new(value, value, value, u8::MAX);
+            }
+        }
+        (_, Some(background), _) => blend_over(&mut visible, background),
+        (_, None, Backdrop::Checkerboard) if uses_alpha => {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let cell = ((CHECKER_SIZE / region.scale).round() as usize).max(1);
+            checkerboard(&mut visible, region.width, (region.x, region.y), cell);
+        }
+        (_, None, Backdrop::Solid) => blend_over(&mut visible, Backdrop::SOLID),
+        (_, None, _) => {}
+    }
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    let scaled = (
+        ((region.width as f64 * region.scale).round() as usize).max(1),
+        ((region.height as f64 * region.scale).round() as usize).max(1),
+    );
+
+    Rendering {
+        pixels: visible,
+        width: region.width,
+        height: region.height,
+        scaled,
+    }
+}
+
+/// Magnified neighborhood of a pixel, with its color and palette index where quantized
+pub fn inspect(preview: &mut Preview, x: usize, y: usize) -> (Vec<RGBA8>, RGBA8, Option<u8>) {
+    let width = preview.source.width;
+    let index = preview.index_at(x, y);
+    let pixels = if index.is_some() {
+        preview.pixels()
+    } else {
+        &preview.source.rgba
+    };
+    (magnify(pixels, width, (x, y)), pixels[y * width + x], index)
+}
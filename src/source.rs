@@ -1,48 +1,187 @@
+use crate::chunks::{write_chunks, Chunk, Position};
+use crate::debug;
 use crate::encode::{Encode, Priority};
-use crate::utilities::RGBs;
-use anyhow::Result;
-use fltk::enums::ColorDepth::{Rgb8, Rgba8};
-use fltk::prelude::ImageExt;
-use png::{ColorType, Encoder};
-use rgb::{ComponentBytes, FromSlice, RGBA8};
+use crate::filter::Filter;
+use crate::utilities::{blend_over, crop, RGBAs, RGBs};
+use anyhow::{bail, Result};
+use png::{chunk, AdaptiveFilterType, BitDepth, ColorType, Decoder, Encoder, Transformations};
+use rgb::{ComponentBytes, FromSlice, RGB8, RGBA8};
+use std::collections::HashSet;
 use std::io::Write;
 
 pub struct Source {
     pub uses_alpha: bool,
+    pub bit_depth_reduced: bool,
+    pub was_indexed: bool,
     pub width: usize,
     pub height: usize,
     pub rgba: Vec<RGBA8>,
+    pub chunks: Vec<Chunk>,
+    pub filter: Option<Filter>,
+}
+
+impl Source {
+    /// Gamma declared by a gAMA chunk, unless an sRGB chunk takes precedence
+    pub fn gamma(&self) -> Option<f64> {
+        if self.chunks.iter().any(|c| c.kind == chunk::sRGB) {
+            return None;
+        }
+        self.chunks
+            .iter()
+            .find(|c| c.kind == chunk::gAMA)
+            .and_then(|c| c.data.as_slice().try_into().ok())
+            .map(|bytes| f64::from(u32::from_be_bytes(bytes)) / 100_000.0)
+            .filter(|gamma| 0.0 < *gamma && *gamma <= 1.0)
+    }
+
+    /// Caveats about quantizing this source
+    pub fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.bit_depth_reduced {
+            warnings.push("Source reduced from 16 to 8 bits per channel");
+        }
+        if self.was_indexed {
+            warnings.push("Source is already indexed; quantizing again compounds loss");
+        }
+        warnings
+    }
+
+    /// Refuse to recompress a source whose depth or color type can’t be kept
+    pub fn check_lossless(&self) -> Result<()> {
+        if self.bit_depth_reduced {
+            bail!("can’t recompress 16 bits per channel losslessly; omit --lossless to quantize");
+        }
+        if self.was_indexed {
+            bail!("source is already indexed; omit --lossless to quantize");
+        }
+        Ok(())
+    }
+
+    /// Number of distinct colors, counting each transparent color separately
+    pub fn unique_colors(&self) -> usize {
+        self.rgba.iter().collect::<HashSet<_>>().len()
+    }
+
+    /// Composite over a solid color, leaving nothing transparent
+    pub fn flatten(&mut self, background: RGB8) {
+        blend_over(&mut self.rgba, background);
+        self.uses_alpha = false;
+    }
+
+    #[must_use]
+    pub fn region(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            uses_alpha: self.uses_alpha,
+            bit_depth_reduced: self.bit_depth_reduced,
+            was_indexed: self.was_indexed,
+            width,
+            height,
+            rgba: crop(&self.rgba, self.width, x, y, width, height),
+            chunks: self.chunks.clone(),
+            filter: self.filter,
+        }
+    }
 }
 
 impl Encode for Source {
     fn encode<W: Write>(&self, priority: Priority, into: W) -> Result<()> {
         let mut encoder = Encoder::new(into, self.width.try_into()?, self.height.try_into()?);
+        match self.filter {
+            Some(filter) => filter.apply(&mut encoder),
+            None if priority == Priority::Size => {
+                encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive);
+            }
+            None => {}
+        }
         encoder.set_compression(priority.into());
-        encoder.set_color(ColorType::Rgba);
+        let is_gray = self.rgba.iter().all(|p| p.r == p.g && p.g == p.b);
+        encoder.set_color(match (is_gray, self.uses_alpha) {
+            (true, true) => ColorType::GrayscaleAlpha,
+            (true, false) => ColorType::Grayscale,
+            (false, true) => ColorType::Rgba,
+            (false, false) => ColorType::Rgb,
+        });
 
-        Ok(encoder
-            .write_header()?
-            .write_image_data(self.rgba.as_bytes())?)
+        let mut writer = encoder.write_header()?;
+        write_chunks(&mut writer, &self.chunks, Position::BeforePlte, is_gray)?;
+        write_chunks(&mut writer, &self.chunks, Position::BeforeIdat, is_gray)?;
+        match (is_gray, self.uses_alpha) {
+            (true, true) => {
+                let samples: Vec<_> = self.rgba.iter().flat_map(|p| [p.g, p.a]).collect();
+                writer.write_image_data(&samples)?;
+            }
+            (true, false) => {
+                let samples: Vec<_> = self.rgba.iter().map(|p| p.g).collect();
+                writer.write_image_data(&samples)?;
+            }
+            (false, true) => writer.write_image_data(self.rgba.as_bytes())?,
+            (false, false) => writer.write_image_data(self.rgba.without_alpha().as_bytes())?,
+        }
+        write_chunks(&mut writer, &self.chunks, Position::AfterIdat, is_gray)?;
+        Ok(writer.finish()?)
     }
 }
 
-impl<I: ImageExt> From<I> for Source {
-    #[allow(clippy::cast_sign_loss)]
-    fn from(image: I) -> Self {
-        match image.depth() {
-            Rgb8 => Self {
-                uses_alpha: false,
-                width: image.width() as usize,
-                height: image.height() as usize,
-                rgba: image.to_rgb_data().as_rgb().with_alpha(),
-            },
-            Rgba8 => Self {
-                uses_alpha: true,
-                width: image.width() as usize,
-                height: image.height() as usize,
-                rgba: image.to_rgb_data().as_rgba().to_owned(),
-            },
-            d => unimplemented!("color mode {:?}", d),
+impl TryFrom<&[u8]> for Source {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(data);
+        decoder.set_transformations(Transformations::EXPAND); // Resolves palette and tRNS
+        let mut reader = decoder.read_info()?;
+        let was_indexed = reader.info().color_type == ColorType::Indexed;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+        let (width, height) = (frame.width.try_into()?, frame.height.try_into()?);
+
+        let bit_depth_reduced = frame.bit_depth == BitDepth::Sixteen;
+        let samples = match frame.bit_depth {
+            BitDepth::Sixteen => narrow(&buffer[..frame.buffer_size()]),
+            _ => buffer[..frame.buffer_size()].to_vec(),
+        };
+
+        let (mut uses_alpha, rgba) = match frame.color_type {
+            ColorType::Rgb => (false, samples.as_rgb().with_alpha()),
+            ColorType::Rgba => (true, samples.as_rgba().to_owned()),
+            ColorType::Grayscale => (false, samples.iter().map(|&l| gray(l, u8::MAX)).collect()),
+            ColorType::GrayscaleAlpha => (
+                true,
+                samples.chunks_exact(2).map(|s| gray(s[0], s[1])).collect(),
+            ),
+            c @ ColorType::Indexed => bail!("unsupported color mode {c:?}"),
+        };
+        if uses_alpha && rgba.iter().all(|p| p.a == u8::MAX) {
+            debug!(
+                "Treating {:?} source as opaque since no pixel uses alpha",
+                frame.color_type
+            );
+            uses_alpha = false;
         }
+
+        Ok(Self {
+            uses_alpha,
+            bit_depth_reduced,
+            was_indexed,
+            width,
+            height,
+            rgba,
+            chunks: Chunk::read_all(data)?,
+            filter: None,
+        })
     }
 }
+
+const fn gray(l: u8, a: u8) -> RGBA8 {
+    RGBA8::new(l, l, l, a)
+}
+
+/// Round big-endian 16-bit samples to 8 bits
+fn narrow(samples: &[u8]) -> Vec<u8> {
+    samples
+        .chunks_exact(2)
+        .map(|s| {
+            let wide = u32::from(u16::from_be_bytes([s[0], s[1]]));
+            u8::try_from((wide * 255 + 32_767) / 65_535).expect("8-bit")
+        })
+        .collect()
+}
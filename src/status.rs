@@ -0,0 +1,92 @@
+use crate::crop::Crop;
+use crate::source::Source;
+use crate::utilities::format_rgba;
+use rgb::RGBA8;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// Summary of how the preview is shown and how its quantization went
+#[derive(Default)]
+pub struct Status {
+    /// Description of how the preview is shown
+    pub view: String,
+    pub palette_region: Option<Crop>,
+    pub draft: bool,
+    pub elapsed: Option<(u8, Duration)>,
+    pub quality: Option<u8>,
+    pub psnr: Option<f64>,
+    pub swatch: Option<(usize, RGBA8)>,
+    pub warnings: Vec<&'static str>,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut fields: Vec<_> = (!self.view.is_empty())
+            .then(|| self.view.clone())
+            .into_iter()
+            .collect();
+        if let Some(region) = self.palette_region {
+            fields.push(format!("Palette from {region}"));
+        }
+        if self.draft {
+            fields.push(String::from("Draft"));
+        }
+        if let Some((effort, elapsed)) = self.elapsed {
+            fields.push(format!(
+                "Effort {effort} quantized in {:.2} s",
+                elapsed.as_secs_f64()
+            ));
+        }
+        if let Some(quality) = self.quality {
+            fields.push(format!("Quality {quality}"));
+        }
+        if let Some(psnr) = self.psnr {
+            fields.push(if psnr.is_finite() {
+                format!("PSNR {psnr:.1} dB")
+            } else {
+                String::from("PSNR ∞")
+            });
+        }
+        if let Some((index, c)) = self.swatch {
+            fields.push(format!("Entry {index} {}", format_rgba(c)));
+        }
+        fields.extend(self.warnings.iter().map(|w| format!("⚠ {w}")));
+        write!(f, "{}", fields.join(" · "))
+    }
+}
+
+/// Facts about the source and the palette found for it
+#[derive(Default)]
+pub struct Details {
+    pub size: (usize, usize),
+    pub uses_alpha: bool,
+    pub unique_colors: usize,
+    pub palette: Option<usize>,
+}
+
+impl From<&Source> for Details {
+    fn from(source: &Source) -> Self {
+        Self {
+            size: (source.width, source.height),
+            uses_alpha: source.uses_alpha,
+            unique_colors: source.unique_colors(),
+            palette: None,
+        }
+    }
+}
+
+impl Display for Details {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (width, height) = self.size;
+        let color = if self.uses_alpha { "RGBA" } else { "RGB" };
+        write!(
+            f,
+            "{width}×{height} {color} · {} colors",
+            self.unique_colors
+        )?;
+        if let Some(palette) = self.palette {
+            write!(f, " · Palette of {palette}")?;
+        }
+        Ok(())
+    }
+}
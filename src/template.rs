@@ -0,0 +1,77 @@
+use crate::preview::Params;
+use std::ffi::{OsStr, OsString};
+use std::path::MAIN_SEPARATOR;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Stem,
+    Suffix,
+    Colors,
+    Quality,
+    Dither,
+    Effort,
+}
+
+/// Output file name, with tokens standing for the source and the parameters exported
+#[derive(Clone, Debug)]
+pub struct NameTemplate(Vec<Part>);
+
+impl NameTemplate {
+    /// Name of the output from a source of the given stem
+    pub fn expand(&self, stem: &OsStr, suffix: &str, params: &Params) -> OsString {
+        let mut name = OsString::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => name.push(text),
+                Part::Stem => name.push(stem),
+                Part::Suffix => name.push(suffix),
+                Part::Colors => name.push(params.colors.to_string()),
+                Part::Quality => name.push(params.preservation.to_string()),
+                Part::Dither => name.push(params.dithering.to_string()),
+                Part::Effort => name.push(params.effort.to_string()),
+            }
+        }
+        name
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['/', MAIN_SEPARATOR]) {
+            return Err(String::from(
+                "must be a file name; use --output for a directory",
+            ));
+        }
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let Some(end) = rest[start..].find('}').map(|e| start + e) else {
+                return Err(String::from("unclosed {"));
+            };
+            parts.push(match &rest[start + 1..end] {
+                "stem" => Part::Stem,
+                "suffix" => Part::Suffix,
+                "colors" => Part::Colors,
+                "quality" => Part::Quality,
+                "dither" => Part::Dither,
+                "effort" => Part::Effort,
+                token => return Err(format!("unknown token {{{token}}}")),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        if parts.is_empty() {
+            return Err(String::from("must not be empty"));
+        }
+        Ok(Self(parts))
+    }
+}
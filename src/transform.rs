@@ -0,0 +1,115 @@
+//! Transforms applied to the source before quantization, whose palette is then recovered from the
+//! untransformed pixels so that the transform only influences how colors are grouped
+
+use rgb::{ColorComponentMap, RGBA8};
+
+pub fn parse_weights(s: &str) -> Result<[f32; 4], String> {
+    let weights: Vec<f32> = s
+        .split(',')
+        .map(|w| w.trim().parse::<f32>().map_err(|e| format!("{e}")))
+        .collect::<Result<_, _>>()?;
+    let weights: [f32; 4] = weights
+        .try_into()
+        .map_err(|_| String::from("expected four weights R,G,B,A"))?;
+    let max = weights.iter().copied().fold(0.0, f32::max);
+    if weights.iter().any(|w| !(0.0..=1.0).contains(w)) || max <= 0.0 {
+        return Err(String::from("weights must be 0–1 and not all zero"));
+    }
+    Ok(weights.map(|w| w / max))
+}
+
+/// Convert sRGB-encoded color channels to linear light, leaving alpha as is
+///
+/// Linear values are kept in 8 bits, which merges the darkest levels of each channel. Since the
+/// palette is recovered from the untransformed pixels, this only coarsens how shadows are grouped.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn linearize(pixels: &[RGBA8]) -> Vec<RGBA8> {
+    let lut: Vec<u8> = (0..=u8::MAX)
+        .map(|c| {
+            let c = f64::from(c) / 255.0;
+            let linear = if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+            (linear * 255.0).round() as u8
+        })
+        .collect();
+    pixels
+        .iter()
+        .map(|p| p.map_colors(|c| lut[usize::from(c)]))
+        .collect()
+}
+
+/// Compress each color channel by its weight so that imagequant considers its differences less
+/// important, leaving alpha as is
+pub fn weigh(pixels: &[RGBA8], weights: [f32; 4]) -> Vec<RGBA8> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let scale = |c: u8, w: f32| (f32::from(c) * w).round() as u8;
+    let [r, g, b, _] = weights;
+    pixels
+        .iter()
+        .map(|p| RGBA8::new(scale(p.r, r), scale(p.g, g), scale(p.b, b), p.a))
+        .collect()
+}
+
+/// Lower the importance of translucent pixels by the alpha weight, since scaling alpha itself
+/// would make pixels more transparent and so discount their color too
+pub fn weigh_importance(
+    pixels: &[RGBA8],
+    importance: Option<&[u8]>,
+    weights: [f32; 4],
+) -> Option<Vec<u8>> {
+    let [.., a] = weights;
+    if a >= 1.0 {
+        return importance.map(<[u8]>::to_vec);
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let scale = |i: u8| (f32::from(i) * a).round() as u8;
+    Some(
+        pixels
+            .iter()
+            .enumerate()
+            .map(|(n, p)| {
+                let base = importance.map_or(u8::MAX, |i| i[n]);
+                if p.a < u8::MAX {
+                    scale(base)
+                } else {
+                    base
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Apply whichever of the above transforms are enabled
+pub fn transform(pixels: &[RGBA8], linear: bool, weights: Option<[f32; 4]>) -> Vec<RGBA8> {
+    let mut pixels = if linear {
+        linearize(pixels)
+    } else {
+        pixels.to_vec()
+    };
+    if let Some(weights) = weights {
+        pixels = weigh(&pixels, weights);
+    }
+    pixels
+}
+
+/// Replace each used palette entry with the mean of the source pixels mapped to it
+pub fn recenter(palette: &mut [RGBA8], pixels: &[RGBA8], indices: &[u8]) {
+    let mut sums = vec![([0_u64; 4], 0_u64); palette.len()];
+    for (p, &i) in pixels.iter().zip(indices) {
+        let (sum, count) = &mut sums[usize::from(i)];
+        for (s, c) in sum.iter_mut().zip([p.r, p.g, p.b, p.a]) {
+            *s += u64::from(c);
+        }
+        *count += 1;
+    }
+    for (entry, (sum, count)) in palette.iter_mut().zip(sums) {
+        if count > 0 {
+            #[allow(clippy::cast_possible_truncation)]
+            let [r, g, b, a] = sum.map(|s| ((s + count / 2) / count) as u8);
+            *entry = RGBA8::new(r, g, b, a);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use rgb::{RGB8, RGBA8};
+use std::ffi::OsStr;
+use std::fmt::Debug;
 use std::io::Write;
+use std::path::PathBuf;
 
 pub struct CachedOption<K: PartialEq, V>(Option<(K, V)>);
 
@@ -12,11 +15,26 @@ impl<K: PartialEq, V> Default for CachedOption<K, V> {
 impl<K: PartialEq, V> CachedOption<K, V> {
     pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         // Pending https://github.com/rust-lang/rust/issues/93050
-        if self.0.as_ref().filter(|&(k, _)| k != &key).is_some() {
+        if self.0.as_ref().is_some_and(|(k, _)| k != &key) {
             self.0.take();
         }
         &mut self.0.get_or_insert_with(|| (key, f())).1
     }
+
+    pub fn get_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, E> {
+        // Pending https://github.com/rust-lang/rust/issues/93050
+        if self.0.as_ref().is_some_and(|(k, _)| k != &key) {
+            self.0.take();
+        }
+        if self.0.is_none() {
+            self.0.replace((key, f()?));
+        }
+        Ok(&mut self.0.as_mut().expect("inserted").1)
+    }
 }
 
 #[derive(Default)]
@@ -26,6 +44,10 @@ impl CountingSink {
     pub const fn len(&self) -> usize {
         self.0
     }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
 }
 
 impl Write for CountingSink {
@@ -59,14 +81,255 @@ pub trait RGBs {
     fn with_alpha(&self) -> Vec<RGBA8>;
 }
 
-impl<'a> RGBs for &'a [RGB8] {
+impl RGBs for &[RGB8] {
     fn with_alpha(&self) -> Vec<RGBA8> {
-        self.iter().map(|rgb| rgb.alpha(u8::MAX)).collect()
+        self.iter().map(|rgb| rgb.with_alpha(u8::MAX)).collect()
     }
 }
 
 // Pending https://github.com/rust-lang/rust/issues/67057
-pub fn u8_from_f64(n: f64) -> u8 {
+pub fn int_from_f64<T: TryFrom<i64>>(n: f64) -> T
+where
+    T::Error: Debug,
+{
     #[allow(clippy::cast_possible_truncation)]
     (n.round() as i64).try_into().unwrap()
 }
+
+pub fn parse_percentage(s: &str) -> Result<f64, String> {
+    let n: f64 = s
+        .strip_suffix('%')
+        .unwrap_or(s)
+        .parse()
+        .map_err(|e| format!("{e}"))?;
+    if n.is_sign_negative() {
+        return Err(String::from("must not be negative"));
+    }
+    Ok(n / 100.0)
+}
+
+pub fn parse_gamma(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| format!("{e}"))? {
+        n if 0.0 < n && n <= 1.0 => Ok(n),
+        _ => Err(String::from(
+            "must be above 0 and at most 1, such as 0.45455",
+        )),
+    }
+}
+
+pub fn parse_hex<const N: usize>(s: &str) -> Result<[u8; N], String> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    if s.len() != N * 2 || !s.is_ascii() {
+        return Err(format!("expected {} hexadecimal digits", N * 2));
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|e| format!("{e}"))?;
+    }
+    Ok(bytes)
+}
+
+pub fn parse_rgb(s: &str) -> Result<RGB8, String> {
+    parse_hex(s).map(|[r, g, b]| RGB8::new(r, g, b))
+}
+
+pub fn parse_rgba(s: &str) -> Result<RGBA8, String> {
+    parse_hex(s).map(|[r, g, b, a]| RGBA8::new(r, g, b, a))
+}
+
+pub fn format_rgba(color: RGBA8) -> String {
+    let RGBA8 { r, g, b, a } = color;
+    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+}
+
+/// Relative change in size, such as "−37%"
+pub fn format_change(size: f64, original: f64) -> String {
+    let percent = (size / original.max(1.0) - 1.0) * 100.0;
+    if percent < 0.0 {
+        format!("−{:.0}%", -percent)
+    } else {
+        format!("+{percent:.0}%")
+    }
+}
+
+/// Every `step`th element of every `step`th row
+pub fn subsample<T: Copy>(data: &[T], stride: usize, step: usize) -> Vec<T> {
+    data.chunks(stride)
+        .step_by(step)
+        .flat_map(|row| row.iter().step_by(step))
+        .copied()
+        .collect()
+}
+
+/// Fewest bits per index that can address a palette of the given length
+pub const fn index_depth(colors: usize) -> u8 {
+    match colors {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    }
+}
+
+/// Pack a row of indices at the given bit depth, leftmost pixel in the high bits
+pub fn pack(indices: &[u8], depth: u8) -> Vec<u8> {
+    if depth == 8 {
+        return indices.to_vec();
+    }
+    let per_byte = usize::from(8 / depth);
+    indices
+        .chunks(per_byte)
+        .map(|pixels| {
+            let byte = pixels.iter().fold(0, |byte, &index| byte << depth | index);
+            byte << (usize::from(depth) * (per_byte - pixels.len()))
+        })
+        .collect()
+}
+
+pub fn crop<T: Copy>(data: &[T], stride: usize, x: usize, y: usize, w: usize, h: usize) -> Vec<T> {
+    data.chunks_exact(stride)
+        .skip(y)
+        .take(h)
+        .flat_map(|row| &row[x..x + w])
+        .copied()
+        .collect()
+}
+
+pub fn parse_palette_path(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    match path.extension().and_then(OsStr::to_str) {
+        Some(extension) if ["act", "gpl"].contains(&extension.to_lowercase().as_str()) => Ok(path),
+        _ => Err(String::from("must end in .gpl or .act")),
+    }
+}
+
+pub fn parse_palette_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>().map_err(|e| format!("{e}"))? {
+        n if n.is_power_of_two() && (2..=256).contains(&n) => Ok(n),
+        _ => Err(String::from("must be a power of two from 2 to 256")),
+    }
+}
+
+/// Path from a line of dropped text, given either as is or as a file URI
+pub fn parse_dropped(line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let Some(uri) = line.strip_prefix("file://") else {
+        return Some(PathBuf::from(line));
+    };
+    // Skip any host, such as localhost
+    let mut rest = &uri.as_bytes()[uri.find('/')?..];
+    let mut decoded = Vec::with_capacity(rest.len());
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(escaped) = escaped {
+            decoded.push(escaped);
+            rest = &tail[2..];
+        } else {
+            decoded.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// Blend pixels over an opaque background
+pub fn blend_over(pixels: &mut [RGBA8], background: RGB8) {
+    for pixel in pixels {
+        *pixel = blend(*pixel, background);
+    }
+}
+
+/// Blend a pixel over an opaque background
+pub fn blend(pixel: RGBA8, background: RGB8) -> RGBA8 {
+    let max = u16::from(u8::MAX);
+    let (a, b) = (u16::from(pixel.a), max - u16::from(pixel.a));
+    let blend = |p: u8, q: u8| {
+        let value = (u16::from(p) * a + u16::from(q) * b + max / 2) / max;
+        u8::try_from(value).expect("blended channel")
+    };
+    RGBA8::new(
+        blend(pixel.r, background.r),
+        blend(pixel.g, background.g),
+        blend(pixel.b, background.b),
+        u8::MAX,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_indices_from_the_high_bits() {
+        assert_eq!(pack(&[1, 0, 1], 1), [0b1010_0000]);
+        assert_eq!(pack(&[3, 2, 1, 0, 3], 2), [0b1110_0100, 0b1100_0000]);
+        assert_eq!(pack(&[15, 1, 7], 4), [0xF1, 0x70]);
+        assert_eq!(pack(&[200, 3], 8), [200, 3]);
+    }
+
+    #[test]
+    fn parses_percentages() {
+        assert_eq!(parse_percentage("50%"), Ok(0.5));
+        assert_eq!(parse_percentage("2.5"), Ok(0.025));
+        assert!(parse_percentage("-1%").is_err());
+        assert!(parse_percentage("half").is_err());
+    }
+
+    #[test]
+    fn parses_gamma() {
+        assert_eq!(parse_gamma("0.45455"), Ok(0.45455));
+        assert_eq!(parse_gamma("1"), Ok(1.0));
+        assert!(parse_gamma("0").is_err());
+        assert!(parse_gamma("2.2").is_err());
+    }
+
+    #[test]
+    fn parses_rgb() {
+        assert_eq!(parse_rgb("#ff8000"), Ok(RGB8::new(0xFF, 0x80, 0)));
+        assert!(parse_rgb("ff80").is_err());
+        assert!(parse_rgb("gg8000").is_err());
+        assert!(parse_rgb("ff80é").is_err());
+    }
+
+    #[test]
+    fn parses_rgba() {
+        assert_eq!(parse_rgba("FF800040"), Ok(RGBA8::new(0xFF, 0x80, 0, 0x40)));
+        assert!(parse_rgba("ff8000").is_err());
+    }
+
+    #[test]
+    fn parses_palette_sizes() {
+        assert_eq!(parse_palette_size("16"), Ok(16));
+        assert!(parse_palette_size("12").is_err());
+        assert!(parse_palette_size("512").is_err());
+    }
+
+    #[test]
+    fn parses_palette_paths() {
+        assert_eq!(parse_palette_path("a.GPL"), Ok(PathBuf::from("a.GPL")));
+        assert!(parse_palette_path("a.png").is_err());
+    }
+
+    #[test]
+    fn parses_dropped_paths() {
+        assert_eq!(
+            parse_dropped("file:///home/a%20b/%C3%A9.png"),
+            Some(PathBuf::from("/home/a b/é.png"))
+        );
+        assert_eq!(
+            parse_dropped("file://localhost/tmp/x.png\r"),
+            Some(PathBuf::from("/tmp/x.png"))
+        );
+        assert_eq!(
+            parse_dropped("/tmp/x y.png"),
+            Some(PathBuf::from("/tmp/x y.png"))
+        );
+        assert_eq!(parse_dropped("# comment"), None);
+    }
+}
@@ -0,0 +1,310 @@
+use pngquant_interactive::crop::Crop;
+use pngquant_interactive::utilities::blend;
+use rgb::{RGB8, RGBA8};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Quantized,
+    Original,
+    SideBySide,
+    Split,
+    Difference,
+}
+
+impl Mode {
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Quantized => Self::Original,
+            Self::Original => Self::SideBySide,
+            Self::SideBySide => Self::Split,
+            Self::Split => Self::Difference,
+            Self::Difference => Self::Quantized,
+        }
+    }
+
+    /// Size of the displayed image for a source of the given size
+    pub const fn extent(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::SideBySide => (width * 2, height),
+            Self::Quantized | Self::Original | Self::Split | Self::Difference => (width, height),
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Quantized => "Quantized",
+            Self::Original => "Original",
+            Self::SideBySide => "Side by side",
+            Self::Split => "Split",
+            Self::Difference => "Difference",
+        })
+    }
+}
+
+/// What transparent pixels are shown against
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backdrop {
+    #[default]
+    Checkerboard,
+    Solid,
+    Transparent,
+}
+
+impl Backdrop {
+    /// Color of a solid backdrop
+    pub const SOLID: RGB8 = RGB8::new(u8::MAX, u8::MAX, u8::MAX);
+
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Checkerboard => Self::Solid,
+            Self::Solid => Self::Transparent,
+            Self::Transparent => Self::Checkerboard,
+        }
+    }
+}
+
+impl Display for Backdrop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Checkerboard => "Checkerboard",
+            Self::Solid => "Solid backdrop",
+            Self::Transparent => "Transparent",
+        })
+    }
+}
+
+/// Component of pixels to show alone, as gray
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    pub const fn of(self, pixel: RGBA8) -> u8 {
+        match self {
+            Self::Red => pixel.r,
+            Self::Green => pixel.g,
+            Self::Blue => pixel.b,
+            Self::Alpha => pixel.a,
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Red => "Red",
+            Self::Green => "Green",
+            Self::Blue => "Blue",
+            Self::Alpha => "Alpha",
+        })
+    }
+}
+
+/// Presentation of the preview within the view
+#[derive(Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub mode: Mode,
+    pub backdrop: Backdrop,
+    pub channel: Option<Channel>,
+    /// Interpolate when scaling rather than taking the nearest pixel
+    pub smooth: bool,
+    /// Show pixels at actual size rather than shrinking to fit the view
+    pub actual: bool,
+    pub crop: Option<Crop>,
+    /// Position of the split between original and quantized, as a fraction of the width
+    pub divider: f64,
+    /// Magnification relative to fitting the view, or to actual size
+    pub zoom: f64,
+    /// Point at the center of the view, as fractions of the displayed image
+    pub center: (f64, f64),
+}
+
+/// Portion of the displayed image that is visible, and the scale at which it’s drawn
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub scale: f64,
+}
+
+impl Display for Viewport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut fields = vec![self.mode.to_string()];
+        if let Some(channel) = self.channel {
+            fields.push(channel.to_string());
+        } else if self.backdrop != Backdrop::default() {
+            fields.push(self.backdrop.to_string());
+        }
+        if let Some(crop) = self.crop {
+            fields.push(format!("Cropped to {}×{}", crop.width, crop.height));
+        }
+        if self.actual {
+            fields.push(String::from("100%"));
+        }
+        if !self.smooth {
+            fields.push(String::from("Nearest"));
+        }
+        write!(f, "{}", fields.join(" · "))
+    }
+}
+
+impl Viewport {
+    pub const MAX_ZOOM: f64 = 64.0;
+
+    /// Size of the displayed image for a source of the given size
+    pub fn extent(&self, width: usize, height: usize) -> (usize, usize) {
+        let (w, h) = self.crop.map_or((width, height), |c| (c.width, c.height));
+        self.mode.extent(w, h)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn region(&self, (ew, eh): (usize, usize), (vw, vh): (usize, usize)) -> Region {
+        let (ew_f, eh_f, vw_f, vh_f) = (ew as f64, eh as f64, vw as f64, vh as f64);
+        let fit = if self.actual {
+            1.0
+        } else {
+            (vw_f / ew_f).min(vh_f / eh_f).min(1.0)
+        };
+        let scale = fit * self.zoom;
+        let (w, h) = ((vw_f / scale).min(ew_f), (vh_f / scale).min(eh_f));
+        let (x, y) = (
+            self.center.0.mul_add(ew_f, -w / 2.0).clamp(0.0, ew_f - w),
+            self.center.1.mul_add(eh_f, -h / 2.0).clamp(0.0, eh_f - h),
+        );
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (x, y) = (x as usize, y as usize);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (width, height) = (
+            (w.ceil() as usize).clamp(1, ew - x),
+            (h.ceil() as usize).clamp(1, eh - y),
+        );
+        Region {
+            x,
+            y,
+            width,
+            height,
+            scale,
+        }
+    }
+
+    /// Find the point of the displayed image under a point in the view, where the visible region
+    /// is centered
+    #[allow(clippy::cast_precision_loss)]
+    pub fn locate(
+        &self,
+        extent: (usize, usize),
+        (vw, vh): (usize, usize),
+        (x, y): (f64, f64),
+    ) -> Option<(f64, f64)> {
+        let region = self.region(extent, (vw, vh));
+        let (dw, dh) = (
+            region.width as f64 * region.scale,
+            region.height as f64 * region.scale,
+        );
+        let (ox, oy) = ((vw as f64 - dw) / 2.0, (vh as f64 - dh) / 2.0);
+        let (ix, iy) = (
+            region.x as f64 + (x - ox) / region.scale,
+            region.y as f64 + (y - oy) / region.scale,
+        );
+
+        let within = (0.0..extent.0 as f64).contains(&ix) && (0.0..extent.1 as f64).contains(&iy);
+        within.then_some((ix, iy))
+    }
+
+    /// Move the visible region by a distance in the view, within the bounds of the displayed image
+    #[allow(clippy::cast_precision_loss)]
+    pub fn pan_by(&mut self, (dx, dy): (f64, f64), extent: (usize, usize), view: (usize, usize)) {
+        let scale = self.region(extent, view).scale;
+        let (ew, eh, vw, vh) = (
+            extent.0 as f64,
+            extent.1 as f64,
+            view.0 as f64,
+            view.1 as f64,
+        );
+        let (hw, hh) = (
+            (vw / scale).min(ew) / ew / 2.0,
+            (vh / scale).min(eh) / eh / 2.0,
+        );
+        self.center = (
+            (self.center.0 - dx / scale / ew).clamp(hw, 1.0 - hw),
+            (self.center.1 - dy / scale / eh).clamp(hh, 1.0 - hh),
+        );
+    }
+
+    /// Magnify by the given factor while keeping the given point of the displayed image in place
+    #[allow(clippy::cast_precision_loss)]
+    pub fn zoom_by(&mut self, factor: f64, extent: (usize, usize), (ix, iy): (f64, f64)) {
+        let zoom = (self.zoom * factor).clamp(1.0, Self::MAX_ZOOM);
+        let ratio = self.zoom / zoom;
+        let (px, py) = (ix / extent.0 as f64, iy / extent.1 as f64);
+        self.center = (
+            (self.center.0 - px).mul_add(ratio, px).clamp(0.0, 1.0),
+            (self.center.1 - py).mul_add(ratio, py).clamp(0.0, 1.0),
+        );
+        self.zoom = zoom;
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            mode: Mode::default(),
+            backdrop: Backdrop::default(),
+            channel: None,
+            smooth: true,
+            actual: false,
+            crop: None,
+            divider: 0.5,
+            zoom: 1.0,
+            center: (0.5, 0.5),
+        }
+    }
+}
+
+/// Render the distance between corresponding pixels from black through red to white, with the
+/// square root emphasizing subtle differences
+pub fn heatmap(original: &[RGBA8], quantized: &[RGBA8]) -> Vec<RGBA8> {
+    let max = f64::from(u8::MAX) * 2.0;
+    original
+        .iter()
+        .zip(quantized)
+        .map(|(o, q)| {
+            let distance = [(o.r, q.r), (o.g, q.g), (o.b, q.b), (o.a, q.a)]
+                .iter()
+                .map(|&(a, b)| f64::from(a.abs_diff(b)).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            let heat = (distance / max).sqrt() * 2.0;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (red, white) = (
+                (heat.min(1.0) * f64::from(u8::MAX)) as u8,
+                ((heat - 1.0).max(0.0) * f64::from(u8::MAX)) as u8,
+            );
+            RGBA8::new(red, white, white, u8::MAX)
+        })
+        .collect()
+}
+
+/// Blend rows of pixels over a checkerboard of light and dark gray cells, aligned to the given
+/// origin so that the pattern stays put as the rows are panned
+pub fn checkerboard(pixels: &mut [RGBA8], stride: usize, (x0, y0): (usize, usize), cell: usize) {
+    let (light, dark) = (RGB8::new(0xcc, 0xcc, 0xcc), RGB8::new(0x99, 0x99, 0x99));
+    for (y, row) in pixels.chunks_mut(stride).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let odd = ((x0 + x) / cell + (y0 + y) / cell) % 2 == 1;
+            *pixel = blend(*pixel, if odd { dark } else { light });
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use crate::args::Args;
+use crate::gui::MARGIN;
+use crate::render::{inspect, Renderer, Rendering};
+use crate::viewport::{Mode, Viewport};
+use crate::{clipboard, loupe};
+use anyhow::{Context, Result};
+use fltk::app;
+use fltk::enums::{Color, ColorDepth};
+use fltk::frame::Frame;
+use fltk::image::{RgbImage, RgbScaling};
+use fltk::misc::Progress;
+use fltk::prelude::*;
+use pngquant_interactive::attributes::AdvancedAttributes;
+use pngquant_interactive::config::Config;
+use pngquant_interactive::encode::{Encode, Priority};
+use pngquant_interactive::export::{is_stdin, original_size, Exporter};
+use pngquant_interactive::preview::{Params, Preview};
+use pngquant_interactive::status::{Details, Status};
+use pngquant_interactive::utilities::{format_change, format_rgba};
+use pngquant_interactive::{info, palette};
+use rgb::{ComponentBytes, RGB8, RGBA8};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, RwLock};
+
+/// Margin by which achieved quality may fall short of preservation before it's highlighted
+const QUALITY_SHORTFALL: u8 = 10;
+
+const WARNING: Color = Color::from_rgb(0xe0, 0xa0, 0x40);
+
+/// Request to the worker
+pub enum Action {
+    Background(RGB8),
+    ClearImportance,
+    Copy,
+    Draft(bool),
+    Export { overwrite: bool },
+    Hover(Option<usize>),
+    Inspect(Option<((usize, usize), (i32, i32))>),
+    Lock(usize),
+    Open(PathBuf),
+    Paint(usize, usize, usize, u8),
+    PaletteFromCrop(bool),
+    Preview,
+    Recolor(usize, RGBA8),
+    Resize,
+    Select(usize),
+    View(Viewport),
+}
+
+/// Notice from the worker to the GUI
+pub enum Event {
+    ConfirmOverwrite(PathBuf),
+    Edit(usize, RGBA8),
+    Error(String),
+    Exported(usize),
+    Opened(PathBuf, (usize, usize)),
+}
+
+/// Quantization, rendering, and exporting away from the GUI thread, with the widgets it updates
+pub struct Worker {
+    pub args: Args,
+    pub exporter: Exporter,
+    pub attributes: AdvancedAttributes,
+    pub config: Config,
+    pub preview: Preview,
+    pub params: Arc<RwLock<Params>>,
+    pub view: Frame,
+    pub spinner: Frame,
+    pub histogram: Frame,
+    pub swatches: Frame,
+    pub gauge: Progress,
+    pub error_label: Frame,
+    pub status_bar: Frame,
+    pub details_bar: Frame,
+    pub loupe: Frame,
+    pub to_app: app::Sender<Event>,
+    pub to_self: mpsc::Sender<Action>,
+    pub to_estimator: mpsc::Sender<(Params, Preview, usize)>,
+}
+
+impl Worker {
+    /// Handle actions as they arrive, reporting failures to the GUI
+    pub fn run(self, for_worker: &mpsc::Receiver<Action>) -> Result<()> {
+        let Self {
+            args,
+            exporter,
+            attributes,
+            mut config,
+            mut preview,
+            params,
+            mut view,
+            mut spinner,
+            mut histogram,
+            mut swatches,
+            mut gauge,
+            mut error_label,
+            mut status_bar,
+            mut details_bar,
+            mut loupe,
+            to_app,
+            to_self,
+            to_estimator,
+        } = self;
+        let mut source_path = args.path().to_path_buf();
+        let mut viewed_params = None;
+        let mut viewed_size = None;
+        let mut viewport = Viewport::default();
+        if args.lossless {
+            viewport.mode = Mode::Original;
+        }
+        let mut renderer = Renderer::default();
+        let mut palette_from_crop = false;
+        RgbImage::set_scaling_algorithm(scaling(&viewport));
+        let mut status = Status {
+            view: viewport.to_string(),
+            draft: args.draft,
+            palette_region: preview.palette_region(),
+            warnings: preview.source.warnings(),
+            ..Status::default()
+        };
+        status_bar.set_label(&status.to_string());
+        let mut details = Details::from(&preview.source);
+        details_bar.set_label(&details.to_string());
+
+        #[allow(clippy::cast_precision_loss)]
+        let mut original_bytes = preview.source.measure(args.estimate_priority())? as f64;
+        let mut source_bytes = original_size(&source_path, &preview.source)?;
+        let targeted = Arc::new(RwLock::new(params.read().expect("params").clone()));
+        follow_progress(&mut preview, &gauge, &params, &targeted);
+
+        let mut handle = |action| -> Result<()> {
+            match action {
+                Action::Background(color) => {
+                    preview.background.replace(color);
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::ClearImportance => {
+                    preview.clear_importance();
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Copy => {
+                    if viewed_params.is_none() {
+                        return Ok(());
+                    }
+                    let mut data = Vec::new();
+                    if args.lossless {
+                        preview.source.encode(Priority::Size, &mut data)?;
+                    } else {
+                        preview.encode(Priority::Size, &mut data)?;
+                    }
+                    clipboard::copy_png(&data).context("Not copied")?;
+                    info!("Copied {} bytes to the clipboard", data.len());
+                }
+                Action::PaletteFromCrop(from_crop) => {
+                    palette_from_crop = from_crop;
+                    preview.set_palette_region(if from_crop {
+                        viewport.crop
+                    } else {
+                        args.palette_region
+                    });
+                    status.palette_region = preview.palette_region();
+                    status_bar.set_label(&status.to_string());
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Draft(draft) => {
+                    preview.draft = draft;
+                    status.draft = draft;
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Hover(swatch) => {
+                    status.swatch = swatch.and_then(|i| Some((i, *preview.palette().get(i)?)));
+                    status_bar.set_label(&status.to_string());
+                    app::awake();
+                }
+                Action::Inspect(None) => {
+                    loupe.hide();
+                    view.redraw();
+                    app::awake();
+                }
+                Action::Inspect(Some(((x, y), cursor))) => {
+                    let (magnified, color, index) = inspect(&mut preview, x, y);
+                    let size = i32::try_from(loupe::SIZE)?;
+                    let image = RgbImage::new(magnified.as_bytes(), size, size, ColorDepth::Rgba8)?;
+                    let hex = format_rgba(color);
+                    loupe.set_image(Some(image));
+                    loupe.set_label(&match index {
+                        Some(index) => format!("{hex} · index {index}"),
+                        None => hex,
+                    });
+
+                    // Follow the cursor within the view
+                    let (lx, ly) = (
+                        (cursor.0 + MARGIN * 2).min(view.x() + view.width() - loupe.width()),
+                        (cursor.1 + MARGIN * 2).min(view.y() + view.height() - loupe.height()),
+                    );
+                    loupe.resize(lx.max(0), ly.max(0), loupe.width(), loupe.height());
+                    loupe.show();
+                    view.redraw();
+                    loupe.redraw();
+                    app::awake();
+                }
+                Action::Open(path) => {
+                    let source = args
+                        .load(&path)
+                        .with_context(|| format!("Couldn’t open {}", path.display()))?;
+                    preview = args.preview(source, &attributes);
+                    renderer = Renderer::default();
+                    viewport.crop = None;
+                    status.view = viewport.to_string();
+                    if palette_from_crop {
+                        preview.set_palette_region(None);
+                    }
+                    status.palette_region = preview.palette_region();
+                    if let Err(e) = config.opened(&path).and_then(|()| config.save()) {
+                        eprintln!("Not remembering recent files: {e}");
+                    }
+                    status.elapsed.take();
+                    status.quality.take();
+                    status.psnr.take();
+                    error_label.set_label("");
+                    status.warnings = preview.source.warnings();
+                    status_bar.set_label(&status.to_string());
+                    details = Details::from(&preview.source);
+                    details_bar.set_label(&details.to_string());
+                    #[allow(clippy::cast_precision_loss)]
+                    let measured = preview.source.measure(args.estimate_priority())? as f64;
+                    original_bytes = measured;
+                    source_bytes = original_size(&path, &preview.source)?;
+                    follow_progress(&mut preview, &gauge, &params, &targeted);
+                    viewed_params.take();
+                    viewed_size.take();
+                    let size = (preview.source.width, preview.source.height);
+                    to_app.send(Event::Opened(path.clone(), size));
+                    source_path = path;
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Lock(index) => {
+                    preview.toggle_lock(index);
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Paint(x, y, radius, strength) => {
+                    preview.paint(x, y, radius, strength);
+                    viewed_params.take();
+                }
+                Action::View(mut v) => {
+                    // Disregard a crop of a previous source
+                    let (sw, sh) = (preview.source.width, preview.source.height);
+                    v.crop = v.crop.filter(|c| c.fits(sw, sh));
+                    if v.crop != viewport.crop && !args.lossless {
+                        if palette_from_crop {
+                            preview.set_palette_region(v.crop);
+                            status.palette_region = preview.palette_region();
+                        }
+                        viewed_params.take();
+                        to_self.send(Action::Preview)?;
+                    }
+                    viewport = v;
+                    status.view = viewport.to_string();
+                    RgbImage::set_scaling_algorithm(scaling(&viewport));
+                    status_bar.set_label(&status.to_string());
+                    if let Some((width, height)) = viewed_size {
+                        view.set_image(Some(to_image(&renderer.display(
+                            &mut preview,
+                            width,
+                            height,
+                            &viewport,
+                        ))?));
+                        view.redraw();
+                    }
+                    app::awake();
+                }
+                Action::Export { overwrite } => {
+                    let params = params.read().expect("params").clone();
+                    let path = exporter.path(&source_path, &params)?;
+                    if !overwrite && !exporter.may_write(&source_path, &path) {
+                        to_app.send(Event::ConfirmOverwrite(path));
+                        return Ok(());
+                    }
+                    if let Some(ref preset) = args.save_preset {
+                        params.save(preset)?;
+                    }
+                    if !is_stdin(&source_path) {
+                        config.remember(&source_path, params.clone())?;
+                        if let Err(e) = config.save() {
+                            eprintln!("Not remembering settings: {e}");
+                        }
+                    }
+                    if args.lossless {
+                        let mut data = Vec::new();
+                        preview.source.encode(Priority::Size, &mut data)?;
+                        let written =
+                            exporter.write(&source_path, &path, &exporter.optimize(&data)?)?;
+                        let original = original_size(&source_path, &preview.source)?;
+                        #[allow(clippy::cast_precision_loss)]
+                        let savings = 1.0 - written as f64 / original.max(1) as f64;
+                        println!(
+                            "{}: {original} → {written} bytes ({:.1}% smaller)",
+                            path.display(),
+                            savings * 100.0
+                        );
+                        to_app.send(Event::Exported(written));
+                        return Ok(());
+                    }
+
+                    params.clone_into(&mut targeted.write().expect("params"));
+                    // Quantize a draft again in full, unless that would discard edits to its palette,
+                    // which already applies to every pixel
+                    if !preview.is_edited() {
+                        preview.draft = false;
+                    }
+                    preview.quantize(&params)?;
+                    let mut data = Vec::new();
+                    preview.encode(Priority::Size, &mut data)?;
+                    let written =
+                        exporter.write(&source_path, &path, &exporter.optimize(&data)?)?;
+                    if !args.exact_estimate {
+                        config.calibrate(preview.estimate()?, data.len());
+                        if let Err(e) = config.save() {
+                            eprintln!("Not remembering size calibration: {e}");
+                        }
+                    }
+                    if let Some(ref path) = args.export_palette {
+                        palette::save(preview.palette(), path)?;
+                    }
+                    if let Some(region) = preview.palette_region() {
+                        info!("Palette derived from the {region} region");
+                    }
+                    if let Some(size) = args.palette_size {
+                        let colors = preview.palette().len();
+                        info!(
+                            "Padded palette of {colors} colors with {} entries",
+                            size - colors
+                        );
+                    }
+                    to_app.send(Event::Exported(written));
+                }
+                Action::Preview => {
+                    let working = params.read().expect("params").clone();
+                    macro_rules! abort_if_untargeted {
+                        () => {
+                            if *params.read().expect("params") != working {
+                                return Ok(());
+                            }
+                        };
+                    }
+                    match &viewed_params {
+                        Some(p) if p == &working => return Ok(()),
+                        _ => {}
+                    }
+                    spinner.show();
+
+                    if args.lossless {
+                        #[allow(clippy::cast_sign_loss)]
+                        let (width, height) = (view.width() as usize, view.height() as usize);
+                        view.set_image(Some(to_image(&renderer.display(
+                            &mut preview,
+                            width,
+                            height,
+                            &viewport,
+                        ))?));
+                        viewed_size.replace((width, height));
+                        viewed_params.replace(working);
+                        spinner.hide();
+                        gauge.set_maximum(original_bytes);
+                        gauge.set_value(original_bytes);
+                        #[allow(clippy::cast_precision_loss)]
+                        gauge.set_label(&format_change(original_bytes, source_bytes as f64));
+                        app::awake();
+                        return Ok(());
+                    }
+
+                    // Quantize
+                    gauge.set_selection_color(Color::Selection);
+                    gauge.set_maximum(100.0);
+                    gauge.set_value(0.0);
+                    gauge.set_label("");
+                    working.clone_into(&mut targeted.write().expect("params"));
+                    match preview.quantize(&working) {
+                        Err(e) if is_aborted(&e) => return Ok(()),
+                        result => result?,
+                    }
+                    let (maximum, original) = match viewport.crop {
+                        Some(c) => {
+                            let region = preview.source.region(c.x, c.y, c.width, c.height);
+                            let size = region.measure(args.estimate_priority())?;
+                            #[allow(clippy::cast_precision_loss)]
+                            (size as f64, size)
+                        }
+                        None => (original_bytes, source_bytes),
+                    };
+                    gauge.set_selection_color(Color::Foreground);
+                    gauge.set_maximum(maximum);
+                    gauge.set_value(0.0);
+                    if let Some(elapsed) = preview.elapsed {
+                        status.elapsed.replace((working.effort, elapsed));
+                    }
+                    status.quality = preview.quality;
+                    details.palette.replace(preview.palette().len());
+                    details_bar.set_label(&details.to_string());
+                    status_bar.set_label(&status.to_string());
+                    status_bar.set_label_color(match preview.quality {
+                        Some(q) if q + QUALITY_SHORTFALL < working.preservation => WARNING,
+                        _ => Color::Foreground,
+                    });
+                    abort_if_untargeted!();
+
+                    // Display
+                    #[allow(clippy::cast_sign_loss)]
+                    let (width, height) = (view.width() as usize, view.height() as usize);
+                    let image =
+                        to_image(&renderer.display(&mut preview, width, height, &viewport))?;
+                    abort_if_untargeted!();
+                    view.set_image(Some(image));
+                    viewed_size.replace((width, height));
+                    spinner.hide();
+                    app::awake();
+
+                    // Chart palette usage
+                    #[allow(clippy::cast_sign_loss)]
+                    let (width, height) = (histogram.width() as usize, histogram.height() as usize);
+                    let chart =
+                        palette::histogram(preview.palette(), &preview.usage(), width, height);
+                    histogram.set_image(Some(RgbImage::new(
+                        chart.as_bytes(),
+                        histogram.width(),
+                        histogram.height(),
+                        ColorDepth::Rgba8,
+                    )?));
+                    histogram.redraw();
+                    #[allow(clippy::cast_sign_loss)]
+                    let (width, height) = (swatches.width() as usize, swatches.height() as usize);
+                    let (grid, width, height) =
+                        palette::swatches(preview.palette(), &preview.locked, width, height);
+                    swatches.set_image(Some(RgbImage::new(
+                        grid.as_bytes(),
+                        width.try_into()?,
+                        height.try_into()?,
+                        ColorDepth::Rgba8,
+                    )?));
+                    swatches.redraw();
+
+                    // Measure quality
+                    let psnr = preview.psnr();
+                    let error = preview.error();
+                    abort_if_untargeted!();
+                    status.psnr.replace(psnr);
+                    status_bar.set_label(&status.to_string());
+                    error_label.set_label(&format!("MSE {error:.2}"));
+
+                    // Estimate size
+                    let snapshot = viewport.crop.map_or_else(
+                        || preview.snapshot(),
+                        |c| preview.region(c.x, c.y, c.width, c.height),
+                    );
+                    to_estimator.send((working.clone(), snapshot, original))?;
+                    viewed_params.replace(working);
+                }
+                Action::Recolor(index, color) => {
+                    preview.recolor(index, color);
+                    status.swatch = Some((index, color));
+                    viewed_params.take();
+                    to_self.send(Action::Preview)?;
+                }
+                Action::Select(index) => {
+                    if let Some(&color) = preview.palette().get(index) {
+                        to_app.send(Event::Edit(index, color));
+                    }
+                }
+                Action::Resize => {
+                    if let Some((pvw, pvh)) = viewed_size {
+                        #[allow(clippy::cast_sign_loss)]
+                        let (vw, vh) = (view.width() as usize, view.height() as usize);
+                        let (w, h) = viewport.extent(preview.source.width, preview.source.height);
+
+                        let zoomed = viewport.zoom > 1.0 || viewport.actual;
+                        let shrunk = vw < pvw || vh < pvh;
+                        let grown = (pvw < vw && pvw < w) || (pvh < vh && pvh < h);
+                        if zoomed || shrunk || grown {
+                            view.set_image(Some(to_image(&renderer.display(
+                                &mut preview,
+                                vw,
+                                vh,
+                                &viewport,
+                            ))?));
+                            viewed_size.replace((vw, vh));
+                            view.redraw();
+                            app::awake();
+                        }
+                    }
+                }
+            }
+            Ok(())
+        };
+        loop {
+            if let Err(e) = handle(for_worker.recv()?) {
+                to_app.send(Event::Error(format!("{e:#}")));
+            }
+        }
+    }
+}
+
+const fn scaling(viewport: &Viewport) -> RgbScaling {
+    if viewport.smooth {
+        RgbScaling::Bilinear
+    } else {
+        RgbScaling::Nearest
+    }
+}
+
+/// Prepare a rendering of the preview to be drawn at its scale
+fn to_image(rendering: &Rendering) -> Result<RgbImage> {
+    let (width, height) = (rendering.width.try_into()?, rendering.height.try_into()?);
+    let mut image = RgbImage::new(
+        rendering.pixels.as_bytes(),
+        width,
+        height,
+        ColorDepth::Rgba8,
+    )?;
+    let (sw, sh) = (
+        rendering.scaled.0.try_into()?,
+        rendering.scaled.1.try_into()?,
+    );
+    if (sw, sh) != (width, height) {
+        image.scale(sw, sh, false, true);
+    }
+    Ok(image)
+}
+
+/// Show the progress of quantization on the gauge, in place of the size estimate until it follows,
+/// abandoning it once the targeted parameters are superseded
+fn follow_progress(
+    preview: &mut Preview,
+    gauge: &Progress,
+    params: &Arc<RwLock<Params>>,
+    targeted: &Arc<RwLock<Params>>,
+) {
+    let (gauge, params, targeted) = (gauge.clone(), params.clone(), targeted.clone());
+    preview.on_progress(move |percent| {
+        if *params.read().expect("params") != *targeted.read().expect("params") {
+            return false;
+        }
+        let mut gauge = gauge.clone();
+        gauge.set_value(f64::from(percent));
+        gauge.redraw();
+        app::awake();
+        true
+    });
+}
+
+fn is_aborted(error: &anyhow::Error) -> bool {
+    error.downcast_ref() == Some(&imagequant::Error::Aborted)
+}